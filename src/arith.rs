@@ -0,0 +1,534 @@
+//! Integer arithmetic for `$((...))`, `((...))` and `let`, with C operator precedence.
+
+use crate::shell::Shell;
+use crate::vars::is_valid_name;
+
+// Variables may hold expressions that refer to further variables.
+const MAX_DEPTH: usize = 128;
+
+// Longest operators first so that tokenizing can stop at the first hit.
+const OPERATORS: [&str; 39] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "=", "!", "~", "&", "^",
+    "|", "?", ":", ",", "(", ")",
+];
+
+const ASSIGNMENTS: [&str; 11] = [
+    "=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", "&=", "^=", "|=",
+];
+
+// Binary operators from loosest to tightest binding.
+const BINARY: [(&str, u8); 19] = [
+    ("||", 1),
+    ("&&", 2),
+    ("|", 3),
+    ("^", 4),
+    ("&", 5),
+    ("==", 6),
+    ("!=", 6),
+    ("<", 7),
+    ("<=", 7),
+    (">", 7),
+    (">=", 7),
+    ("<<", 8),
+    (">>", 8),
+    ("+", 9),
+    ("-", 9),
+    ("*", 10),
+    ("/", 10),
+    ("%", 10),
+    ("**", 11),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(i64),
+    Variable(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Assign(String, &'static str, Box<Expr>),
+    Increment {
+        name: String,
+        delta: i64,
+        prefix: bool,
+    },
+}
+
+/// Evaluates an already expanded arithmetic expression; an empty one is zero.
+pub fn evaluate(expr: &str, shell: &mut Shell) -> Result<i64, String> {
+    evaluate_nested(expr, shell, 0).map_err(|e| format!("{}: {e}", expr.trim()))
+}
+
+fn evaluate_nested(expr: &str, shell: &mut Shell, depth: usize) -> Result<i64, String> {
+    if depth > MAX_DEPTH {
+        return Err("expression recursion level exceeded".to_string());
+    }
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let ast = parser.comma()?;
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        return Err(format!(
+            "syntax error in expression (error token is \"{}\")",
+            token_text(token)
+        ));
+    }
+    eval(&ast, shell, depth)
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || matches!(chars[i], '#' | '@' | '_'))
+            {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(parse_number(&literal)?));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..].iter().take(3).collect();
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| {
+                    format!(
+                        "syntax error: invalid arithmetic operator (error token is \"{}\")",
+                        chars[i..].iter().collect::<String>()
+                    )
+                })?;
+            i += op.len();
+            tokens.push(Token::Op(op));
+        }
+    }
+    Ok(tokens)
+}
+
+// Accepts decimal, octal with a leading 0, hex with 0x and `base#digits`.
+fn parse_number(literal: &str) -> Result<i64, String> {
+    let invalid = || format!("value too great for base (error token is \"{literal}\")");
+    let (base, digits) = if let Some((base, digits)) = literal.split_once('#') {
+        let base: u32 = base
+            .parse()
+            .map_err(|_| format!("invalid arithmetic base (error token is \"{literal}\")"))?;
+        if !(2..=64).contains(&base) {
+            return Err(format!(
+                "invalid arithmetic base (error token is \"{literal}\")"
+            ));
+        }
+        (base, digits)
+    } else if let Some(hex) = literal
+        .strip_prefix("0x")
+        .or_else(|| literal.strip_prefix("0X"))
+    {
+        (16, hex)
+    } else if literal.len() > 1 && literal.starts_with('0') {
+        (8, &literal[1..])
+    } else {
+        (10, literal)
+    };
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+        if digit >= base {
+            return Err(invalid());
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Ok(value)
+}
+
+fn token_text(token: &Token) -> String {
+    match token {
+        Token::Number(n) => n.to_string(),
+        Token::Name(name) => name.clone(),
+        Token::Op(op) => op.to_string(),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            return Ok(());
+        }
+        match self.tokens.get(self.pos) {
+            Some(token) => Err(format!(
+                "syntax error in expression (error token is \"{}\")",
+                token_text(token)
+            )),
+            None => Err(format!("syntax error: `{op}' expected")),
+        }
+    }
+
+    fn comma(&mut self) -> Result<Expr, String> {
+        let mut expr = self.assignment()?;
+        while self.peek_op() == Some(",") {
+            self.pos += 1;
+            expr = Expr::Binary(",", Box::new(expr), Box::new(self.assignment()?));
+        }
+        Ok(expr)
+    }
+
+    fn assignment(&mut self) -> Result<Expr, String> {
+        if let Some(Token::Name(name)) = self.tokens.get(self.pos)
+            && let Some(Token::Op(op)) = self.tokens.get(self.pos + 1)
+            && ASSIGNMENTS.contains(op)
+        {
+            let name = name.clone();
+            let op: &'static str = op;
+            self.pos += 2;
+            let value = self.assignment()?;
+            return Ok(Expr::Assign(name, op, Box::new(value)));
+        }
+        let expr = self.conditional()?;
+        match self.peek_op() {
+            Some(op) if ASSIGNMENTS.contains(&op) => {
+                Err("attempted assignment to non-variable".to_string())
+            }
+            _ => Ok(expr),
+        }
+    }
+
+    fn conditional(&mut self) -> Result<Expr, String> {
+        let condition = self.binary(1)?;
+        if self.peek_op() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let then = self.assignment()?;
+        self.expect(":")?;
+        let otherwise = self.conditional()?;
+        Ok(Expr::Conditional(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.peek_op()
+            && let Some(&(op, precedence)) = BINARY.iter().find(|(binary, _)| *binary == op)
+            && precedence >= min_precedence
+        {
+            self.pos += 1;
+            // `**` is right associative; everything else groups to the left.
+            let next = if op == "**" {
+                precedence
+            } else {
+                precedence + 1
+            };
+            let rhs = self.binary(next)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek_op() {
+            Some(op @ ("++" | "--")) => {
+                self.pos += 1;
+                match self.tokens.get(self.pos) {
+                    Some(Token::Name(name)) => {
+                        let name = name.clone();
+                        self.pos += 1;
+                        Ok(Expr::Increment {
+                            name,
+                            delta: if op == "++" { 1 } else { -1 },
+                            prefix: true,
+                        })
+                    }
+                    // Without a variable, `--x` is just two negations.
+                    _ => {
+                        let sign = if op == "++" { "+" } else { "-" };
+                        let operand = Expr::Unary(sign, Box::new(self.unary()?));
+                        Ok(Expr::Unary(sign, Box::new(operand)))
+                    }
+                }
+            }
+            Some(op @ ("+" | "-" | "!" | "~")) => {
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.unary()?)))
+            }
+            _ => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let expr = self.primary()?;
+        if let Expr::Variable(name) = &expr
+            && let Some(op @ ("++" | "--")) = self.peek_op()
+        {
+            self.pos += 1;
+            return Ok(Expr::Increment {
+                name: name.clone(),
+                delta: if op == "++" { 1 } else { -1 },
+                prefix: false,
+            });
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Name(name)) => Ok(Expr::Variable(name)),
+            Some(Token::Op("(")) => {
+                let expr = self.comma()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(token) => Err(format!(
+                "syntax error: operand expected (error token is \"{}\")",
+                token_text(&token)
+            )),
+            None => Err("syntax error: operand expected".to_string()),
+        }
+    }
+}
+
+fn variable(name: &str, shell: &mut Shell, depth: usize) -> Result<i64, String> {
+    let value = shell.vars().get(name).unwrap_or_default().to_string();
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(0);
+    }
+    match value.parse() {
+        Ok(n) => Ok(n),
+        Err(_) => evaluate_nested(value, shell, depth + 1),
+    }
+}
+
+fn assign(name: &str, value: i64, shell: &mut Shell) -> Result<i64, String> {
+    if !is_valid_name(name) {
+        return Err("attempted assignment to non-variable".to_string());
+    }
+    shell.vars_mut().set(name, value.to_string());
+    Ok(value)
+}
+
+fn eval(expr: &Expr, shell: &mut Shell, depth: usize) -> Result<i64, String> {
+    match expr {
+        Expr::Number(n) => Ok(*n),
+        Expr::Variable(name) => variable(name, shell, depth),
+        Expr::Unary(op, operand) => {
+            let value = eval(operand, shell, depth)?;
+            Ok(match *op {
+                "-" => value.wrapping_neg(),
+                "!" => (value == 0) as i64,
+                "~" => !value,
+                _ => value,
+            })
+        }
+        Expr::Binary("&&", lhs, rhs) => {
+            Ok((eval(lhs, shell, depth)? != 0 && eval(rhs, shell, depth)? != 0) as i64)
+        }
+        Expr::Binary("||", lhs, rhs) => {
+            Ok((eval(lhs, shell, depth)? != 0 || eval(rhs, shell, depth)? != 0) as i64)
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let lhs = eval(lhs, shell, depth)?;
+            let rhs = eval(rhs, shell, depth)?;
+            apply(op, lhs, rhs)
+        }
+        Expr::Conditional(condition, then, otherwise) => {
+            if eval(condition, shell, depth)? != 0 {
+                eval(then, shell, depth)
+            } else {
+                eval(otherwise, shell, depth)
+            }
+        }
+        Expr::Assign(name, op, value) => {
+            let mut value = eval(value, shell, depth)?;
+            if let Some(op) = op.strip_suffix('=')
+                && !op.is_empty()
+            {
+                value = apply(op, variable(name, shell, depth)?, value)?;
+            }
+            assign(name, value, shell)
+        }
+        Expr::Increment {
+            name,
+            delta,
+            prefix,
+        } => {
+            let old = variable(name, shell, depth)?;
+            let new = assign(name, old.wrapping_add(*delta), shell)?;
+            Ok(if *prefix { new } else { old })
+        }
+    }
+}
+
+fn apply(op: &str, lhs: i64, rhs: i64) -> Result<i64, String> {
+    Ok(match op {
+        "," => rhs,
+        "|" => lhs | rhs,
+        "^" => lhs ^ rhs,
+        "&" => lhs & rhs,
+        "==" => (lhs == rhs) as i64,
+        "!=" => (lhs != rhs) as i64,
+        "<" => (lhs < rhs) as i64,
+        "<=" => (lhs <= rhs) as i64,
+        ">" => (lhs > rhs) as i64,
+        ">=" => (lhs >= rhs) as i64,
+        "<<" => lhs.wrapping_shl(rhs as u32),
+        ">>" => lhs.wrapping_shr(rhs as u32),
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 => return Err("division by 0".to_string()),
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        "**" if rhs < 0 => return Err("exponent less than 0".to_string()),
+        "**" => lhs.wrapping_pow(rhs.min(u32::MAX as i64) as u32),
+        _ => {
+            return Err(format!(
+                "syntax error: invalid arithmetic operator (error token is \"{op}\")"
+            ));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_str(expr: &str) -> Result<i64, String> {
+        evaluate(expr, &mut Shell::new())
+    }
+
+    #[test]
+    fn test_arith_precedence() {
+        assert_eq!(eval_str("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval_str("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval_str("10 - 4 - 3"), Ok(3));
+        assert_eq!(eval_str("2 ** 3 ** 2"), Ok(512));
+        assert_eq!(eval_str("-2 ** 2"), Ok(4));
+        assert_eq!(eval_str("7 % 3 + 8 / 3"), Ok(3));
+        assert_eq!(eval_str("1 << 4 | 1"), Ok(17));
+        assert_eq!(eval_str("3 > 2 && 2 >= 2 || 0"), Ok(1));
+        assert_eq!(eval_str("!0 + ~0"), Ok(0));
+        assert_eq!(eval_str(""), Ok(0));
+    }
+
+    #[test]
+    fn test_arith_number_bases() {
+        assert_eq!(eval_str("0x1f"), Ok(31));
+        assert_eq!(eval_str("017"), Ok(15));
+        assert_eq!(eval_str("2#101"), Ok(5));
+        assert_eq!(eval_str("16#FF"), Ok(255));
+        assert!(eval_str("09").is_err());
+    }
+
+    #[test]
+    fn test_arith_variables_and_assignment() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("x", "5".to_string());
+        shell.vars_mut().set("expr", "x * 2".to_string());
+        shell.vars_mut().unset("unset_var");
+        assert_eq!(evaluate("x + 1", &mut shell), Ok(6));
+        assert_eq!(evaluate("expr + 1", &mut shell), Ok(11));
+        assert_eq!(evaluate("unset_var + 1", &mut shell), Ok(1));
+        assert_eq!(evaluate("y = x += 2", &mut shell), Ok(7));
+        assert_eq!(shell.vars().get("x"), Some("7"));
+        assert_eq!(shell.vars().get("y"), Some("7"));
+        assert_eq!(evaluate("x <<= 1, x", &mut shell), Ok(14));
+    }
+
+    #[test]
+    fn test_arith_increment() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("i", "1".to_string());
+        assert_eq!(evaluate("i++", &mut shell), Ok(1));
+        assert_eq!(evaluate("++i", &mut shell), Ok(3));
+        assert_eq!(evaluate("i--", &mut shell), Ok(3));
+        assert_eq!(evaluate("--i", &mut shell), Ok(1));
+        assert_eq!(evaluate("--5", &mut shell), Ok(5));
+    }
+
+    #[test]
+    fn test_arith_ternary_short_circuits() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("a", "0".to_string());
+        assert_eq!(evaluate("1 ? 10 : (a = 5)", &mut shell), Ok(10));
+        assert_eq!(shell.vars().get("a"), Some("0"));
+        assert_eq!(evaluate("0 || (a = 2)", &mut shell), Ok(1));
+        assert_eq!(evaluate("0 && (a = 3)", &mut shell), Ok(0));
+        assert_eq!(shell.vars().get("a"), Some("2"));
+        assert_eq!(evaluate("a ? 1 : 0 ? 2 : 3", &mut shell), Ok(1));
+    }
+
+    #[test]
+    fn test_arith_errors() {
+        assert_eq!(eval_str("1 / 0"), Err("1 / 0: division by 0".to_string()));
+        assert_eq!(eval_str("5 % 0"), Err("5 % 0: division by 0".to_string()));
+        assert!(eval_str("1 +").is_err());
+        assert!(eval_str("(1").is_err());
+        assert!(eval_str("1 2").is_err());
+        assert!(eval_str("2 ** -1").is_err());
+        assert!(eval_str("3 = 4").is_err());
+        assert!(eval_str("1 $ 2").is_err());
+    }
+
+    #[test]
+    fn test_arith_recursive_variable() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("loop", "loop".to_string());
+        assert!(evaluate("loop", &mut shell).is_err());
+    }
+
+    #[test]
+    fn test_arith_overflow_wraps() {
+        assert_eq!(eval_str("9223372036854775807 + 1"), Ok(i64::MIN));
+        assert_eq!(eval_str("(-9223372036854775807 - 1) / -1"), Ok(i64::MIN));
+    }
+}
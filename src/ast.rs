@@ -0,0 +1,130 @@
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct List {
+    pub items: Vec<ListItem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem {
+    pub and_or: AndOr,
+    pub background: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Connector {
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub negated: bool,
+    pub commands: Vec<Command>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    Compound(CompoundCommand, Vec<Redirect>),
+    /// `name() body` or `function name body`; the body is shared with the
+    /// shell once the definition runs.
+    FunctionDefinition {
+        name: String,
+        body: Rc<Command>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompoundCommand {
+    Subshell(List),
+    BraceGroup(List),
+    Arithmetic(String),
+    /// `if` with its `elif` branches as (condition, body) pairs, then an optional `else`.
+    If {
+        branches: Vec<(List, List)>,
+        else_body: Option<List>,
+    },
+    /// `for name in words; do body; done`; without `in` it loops over the positional parameters.
+    For {
+        name: String,
+        words: Option<Vec<String>>,
+        body: List,
+    },
+    /// `for ((init; condition; update)); do body; done`.
+    ArithmeticFor {
+        init: String,
+        condition: String,
+        update: String,
+        body: List,
+    },
+    /// `while` loop, or `until` loop when `until` is set.
+    While {
+        condition: List,
+        body: List,
+        until: bool,
+    },
+    Case {
+        word: String,
+        items: Vec<CaseItem>,
+    },
+}
+
+/// One `pattern | pattern) body` clause of a `case` command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseItem {
+    pub patterns: Vec<String>,
+    pub body: List,
+    pub terminator: CaseTerminator,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaseTerminator {
+    /// `;;` ends the `case` command.
+    Break,
+    /// `;&` runs the next body without testing its patterns.
+    FallThrough,
+    /// `;;&` goes on testing the next patterns.
+    Continue,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<String>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// A leading `NAME=value` word; the value is kept raw for expansion at run time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    pub fd: i32,
+    pub kind: RedirectKind,
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedirectKind {
+    Input,
+    Output,
+    Append,
+    ReadWrite,
+    DupInput,
+    DupOutput,
+    OutputAll,
+    AppendAll,
+    HereDoc { expand: bool },
+    HereString,
+}
@@ -0,0 +1,291 @@
+//! Brace expansion, the first expansion applied to a word.
+//!
+//! `pre{a,b}post` becomes one word per comma-separated alternative, and
+//! `{x..y[..incr]}` counts between two integers or two letters. Braces inside
+//! quotes or `${...}` and `$(...)` are left alone, as are braces without a
+//! comma or a valid sequence.
+
+/// Expands the braces of a raw word into the raw words they stand for.
+pub fn expand_braces(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let Some((open, close, alternatives)) = find_expansion(&chars) else {
+        return vec![word.to_string()];
+    };
+    let preamble: String = chars[..open].iter().collect();
+    let postscript: String = chars[close + 1..].iter().collect();
+    let tails = expand_braces(&postscript);
+    let mut words = Vec::new();
+    for alternative in alternatives {
+        for middle in expand_braces(&alternative) {
+            for tail in &tails {
+                words.push(format!("{preamble}{middle}{tail}"));
+            }
+        }
+    }
+    words
+}
+
+// Finds the first brace pair that expands, returning its positions and the
+// raw words it produces.
+fn find_expansion(chars: &[char]) -> Option<(usize, usize, Vec<String>)> {
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some(next) = skip_protected(chars, i) {
+            i = next;
+            continue;
+        }
+        if chars[i] == '{'
+            && let Some(close) = matching_brace(chars, i)
+        {
+            let body = &chars[i + 1..close];
+            let parts = split_commas(body);
+            if parts.len() > 1 {
+                return Some((i, close, parts));
+            }
+            let body: String = body.iter().collect();
+            if let Some(sequence) = sequence(&body) {
+                return Some((i, close, sequence));
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+// Returns the index just past a quoted part or a `$` expansion starting at `i`,
+// whose braces do not take part in brace expansion.
+fn skip_protected(chars: &[char], i: usize) -> Option<usize> {
+    match chars[i] {
+        '\\' => Some((i + 2).min(chars.len())),
+        '\'' => Some(
+            chars[i + 1..]
+                .iter()
+                .position(|&c| c == '\'')
+                .map_or(chars.len(), |end| i + end + 2),
+        ),
+        '"' | '`' => {
+            let quote = chars[i];
+            let mut j = i + 1;
+            while j < chars.len() && chars[j] != quote {
+                j = match chars[j] {
+                    '\\' => j + 2,
+                    '$' if quote == '"' => skip_protected(chars, j).unwrap_or(j + 1),
+                    _ => j + 1,
+                };
+            }
+            Some((j + 1).min(chars.len()))
+        }
+        '$' => match chars.get(i + 1) {
+            Some('{') => Some(skip_balanced(chars, i + 1, '{', '}').unwrap_or(chars.len())),
+            Some('(') => Some(skip_balanced(chars, i + 1, '(', ')').unwrap_or(chars.len())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Returns the index just past the `close` that balances the `open` at `i`.
+fn skip_balanced(chars: &[char], i: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    let mut j = i;
+    while j < chars.len() {
+        if j > i
+            && let Some(next) = skip_protected(chars, j)
+        {
+            j = next;
+            continue;
+        }
+        if chars[j] == open {
+            depth += 1;
+        } else if chars[j] == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(j + 1);
+            }
+        }
+        j += 1;
+    }
+    None
+}
+
+fn matching_brace(chars: &[char], open: usize) -> Option<usize> {
+    let close = skip_balanced(chars, open, '{', '}')? - 1;
+    (close > open + 1).then_some(close)
+}
+
+// Splits a brace body at its top-level commas.
+fn split_commas(body: &[char]) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut depth = 0;
+    let mut i = 0;
+    while i < body.len() {
+        if let Some(next) = skip_protected(body, i) {
+            parts.last_mut().unwrap().extend(&body[i..next]);
+            i = next;
+            continue;
+        }
+        match body[i] {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(String::new());
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        parts.last_mut().unwrap().push(body[i]);
+        i += 1;
+    }
+    parts
+}
+
+// Expands `x..y` or `x..y..incr` between integers or single letters.
+fn sequence(body: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = body.split("..").collect();
+    let (start, end, step) = match parts[..] {
+        [start, end] => (start, end, 1),
+        [start, end, step] => (start, end, step.parse::<i64>().ok()?),
+        _ => return None,
+    };
+    let step = step.unsigned_abs().max(1);
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let padded = |s: &str| {
+            s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0')
+        };
+        let width = if padded(start) || padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        return Some(
+            count(first, last, step)
+                .into_iter()
+                .map(|n| {
+                    if n < 0 {
+                        format!(
+                            "-{:0width$}",
+                            n.unsigned_abs(),
+                            width = width.saturating_sub(1)
+                        )
+                    } else {
+                        format!("{n:0width$}")
+                    }
+                })
+                .collect(),
+        );
+    }
+    let (mut start_chars, mut end_chars) = (start.chars(), end.chars());
+    match (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) {
+        (Some(first), None, Some(last), None)
+            if first.is_ascii_alphabetic() && last.is_ascii_alphabetic() =>
+        {
+            Some(
+                count(first as i64, last as i64, step)
+                    .into_iter()
+                    .map(|n| {
+                        let c = n as u8 as char;
+                        // Characters between `Z` and `a` are punctuation that
+                        // the later expansions must see as literal.
+                        if c.is_ascii_alphanumeric() {
+                            c.to_string()
+                        } else {
+                            format!("\\{c}")
+                        }
+                    })
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+fn count(first: i64, last: i64, step: u64) -> Vec<i64> {
+    if first <= last {
+        (first..=last).step_by(step as usize).collect()
+    } else {
+        (last..=first).rev().step_by(step as usize).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(word: &str) -> Vec<String> {
+        expand_braces(word)
+    }
+
+    #[test]
+    fn test_brace_alternatives() {
+        assert_eq!(
+            expand("src/{lib,bin,tests}"),
+            vec!["src/lib", "src/bin", "src/tests"]
+        );
+        assert_eq!(expand("a{b,}c"), vec!["abc", "ac"]);
+        assert_eq!(expand("{a,b}{1,2}"), vec!["a1", "a2", "b1", "b2"]);
+    }
+
+    #[test]
+    fn test_brace_nested() {
+        assert_eq!(expand("x{a,b{1,2},c}"), vec!["xa", "xb1", "xb2", "xc"]);
+        assert_eq!(expand("{a{b,c}}"), vec!["{ab}", "{ac}"]);
+    }
+
+    #[test]
+    fn test_brace_sequences() {
+        assert_eq!(expand("{1..4}"), vec!["1", "2", "3", "4"]);
+        assert_eq!(expand("{1..10..3}"), vec!["1", "4", "7", "10"]);
+        assert_eq!(expand("{3..1}"), vec!["3", "2", "1"]);
+        assert_eq!(expand("{5..1..-2}"), vec!["5", "3", "1"]);
+        assert_eq!(
+            expand("file{01..03}.txt"),
+            vec!["file01.txt", "file02.txt", "file03.txt"]
+        );
+        assert_eq!(expand("{-02..1}"), vec!["-02", "-01", "000", "001"]);
+        assert_eq!(
+            expand("{-9223372036854775808..-9223372036854775807}"),
+            vec!["-9223372036854775808", "-9223372036854775807"]
+        );
+        assert_eq!(
+            expand("{9223372036854775806..9223372036854775807}"),
+            vec!["9223372036854775806", "9223372036854775807"]
+        );
+        assert_eq!(
+            expand("{-09223372036854775808..-09223372036854775808}"),
+            vec!["-09223372036854775808"]
+        );
+        assert_eq!(expand("{a..e..2}"), vec!["a", "c", "e"]);
+        assert_eq!(expand("{C..A}"), vec!["C", "B", "A"]);
+        assert_eq!(
+            expand("{Y..b}"),
+            vec![
+                "Y", "Z", "\\[", "\\\\", "\\]", "\\^", "\\_", "\\`", "a", "b"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_brace_left_alone() {
+        assert_eq!(expand("{a}"), vec!["{a}"]);
+        assert_eq!(expand("{}"), vec!["{}"]);
+        assert_eq!(expand("{a,b"), vec!["{a,b"]);
+        assert_eq!(expand("{1..x}"), vec!["{1..x}"]);
+        assert_eq!(expand("'{a,b}'"), vec!["'{a,b}'"]);
+        assert_eq!(expand("\"{a,b}\""), vec!["\"{a,b}\""]);
+        assert_eq!(expand("\\{a,b}"), vec!["\\{a,b}"]);
+        assert_eq!(expand("${x:-a,b}"), vec!["${x:-a,b}"]);
+        assert_eq!(expand("$(echo {a,b})"), vec!["$(echo {a,b})"]);
+    }
+
+    #[test]
+    fn test_brace_keeps_quotes_in_alternatives() {
+        assert_eq!(expand("{'a b',\"c,d\"}"), vec!["'a b'", "\"c,d\""]);
+        assert_eq!(expand("${x}{1,2}"), vec!["${x}1", "${x}2"]);
+    }
+}
@@ -1,8 +1,13 @@
+use crate::arith::evaluate;
 use crate::cmd::{CmdFn, Execute};
+use crate::exec::quote;
 use crate::external::external_command_exists;
-use crate::shell::Shell;
+use crate::options::ShellOptions;
+use crate::shell::{Control, Shell};
+use crate::vars::is_valid_name;
 use std::collections::HashMap;
 use std::env;
+use std::io::Write;
 use std::path::Path;
 
 pub struct BuiltinCommand<'a> {
@@ -19,72 +24,357 @@ impl<'a> BuiltinCommand<'a> {
     }
 }
 
-fn echo(args: &[String], _shell: &mut Shell) -> Result<(), String> {
+fn write_error(name: &str, err: std::io::Error) -> String {
+    format!("{name}: write error: {err}")
+}
+
+fn echo(args: &[String], shell: &mut Shell) -> Result<i32, String> {
     let output = args.join(" ");
-    println!("{output}");
-    Ok(())
+    writeln!(shell.stdout(), "{output}").map_err(|e| write_error("echo", e))?;
+    Ok(0)
 }
 
-fn exit(_args: &[String], _shell: &mut Shell) -> Result<(), String> {
-    std::process::exit(0);
+fn exit(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    let code = match args.first() {
+        Some(arg) => arg
+            .parse::<i32>()
+            .map_err(|_| format!("exit: {arg}: numeric argument required"))?,
+        None => shell.last_status(),
+    } & 0xff;
+    shell.set_control(Some(Control::Exit(code)));
+    Ok(code)
 }
 
-fn pwd(_args: &[String], _shell: &mut Shell) -> Result<(), String> {
+fn pwd(_args: &[String], shell: &mut Shell) -> Result<i32, String> {
     let current_dir = env::current_dir().map_err(|e| e.to_string())?;
-    println!("The current directory is: {}", current_dir.display());
-    Ok(())
+    writeln!(
+        shell.stdout(),
+        "The current directory is: {}",
+        current_dir.display()
+    )
+    .map_err(|e| write_error("pwd", e))?;
+    Ok(0)
+}
+
+fn cd(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    let path = match args {
+        [] => match env::home_dir() {
+            Some(path) => path,
+            None => return Err("Home Directory not Found".to_string()),
+        },
+        [path] => Path::new(path).to_path_buf(),
+        _ => return Err("CD only 1 takes 1 Path".to_string()),
+    };
+    let old = env::current_dir().ok();
+    env::set_current_dir(path).map_err(|e| e.to_string())?;
+    // Keep PWD and OLDPWD current for `~+` and `~-`.
+    if let Some(old) = old {
+        let old = shell.vars().get("PWD").map_or(old, Into::into);
+        shell.vars_mut().set("OLDPWD", old.display().to_string());
+    }
+    let dir = env::current_dir().map_err(|e| e.to_string())?;
+    shell.vars_mut().set("PWD", dir.display().to_string());
+    Ok(0)
+}
+
+fn history_cmd(_args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    let mut out = shell.stdout();
+    for (i, line) in shell.history().iter().enumerate() {
+        writeln!(out, "{} {line}", i + 1).map_err(|e| write_error("history", e))?;
+    }
+    Ok(0)
+}
+
+fn type_cmd(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    let dispatch_table = build_dispatch_table();
+    let mut out = shell.stdout();
+    if let Some(arg) = args.first() {
+        let result = if arg.chars().all(char::is_whitespace) {
+            writeln!(out)
+        } else if shell.function(arg).is_some() {
+            writeln!(out, "{arg} : FUNCTION")
+        } else if dispatch_table.contains_key(arg) {
+            writeln!(out, "{arg} : BUILTIN")
+        } else if external_command_exists(arg, shell) {
+            writeln!(out, "{arg} : EXTERNAL")
+        } else {
+            writeln!(out, "{arg} : UNKNOW COMMAND")
+        };
+        result.map_err(|e| write_error("type", e))?;
+    }
+    Ok(0)
 }
 
-fn cd(args: &[String], _shell: &mut Shell) -> Result<(), String> {
+fn export(args: &[String], shell: &mut Shell) -> Result<i32, String> {
     if args.is_empty() {
-        match env::home_dir() {
-            Some(path) => {
-                env::set_current_dir(path).map_err(|e| e.to_string())?;
-                return Ok(());
-            }
-            _ => return Err("Home Directory not Found".to_string()),
+        let mut exported: Vec<(&str, &str)> = shell.vars().exported().collect();
+        exported.sort();
+        let mut out = shell.stdout();
+        for (name, value) in exported {
+            writeln!(out, "export {name}=\"{value}\"").map_err(|e| write_error("export", e))?;
         }
+        return Ok(0);
     }
-    if args.len() > 1 {
-        return Err("CD only 1 takes 1 Path".to_string());
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !is_valid_name(name) {
+            return Err(format!("export: `{arg}': not a valid identifier"));
+        }
+        if let Some(value) = value {
+            shell.vars_mut().set(name, value.to_string());
+        }
+        shell.vars_mut().export(name);
     }
-    let path_str = args.join("");
-    let path = Path::new(&path_str);
-    env::set_current_dir(path).map_err(|e| e.to_string())?;
-    Ok(())
+    Ok(0)
 }
 
-fn history_cmd(_args: &[String], shell: &mut Shell) -> Result<(), String> {
-    let mut i: u32 = 1;
-    for line in shell.history().iter() {
-        println!("{i} {line}");
-        i += 1;
+fn unset(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    let functions = args.iter().any(|arg| arg == "-f");
+    for arg in args.iter().filter(|arg| *arg != "-v" && *arg != "-f") {
+        if functions {
+            shell.unset_function(arg);
+            continue;
+        }
+        if !is_valid_name(arg) {
+            return Err(format!("unset: `{arg}': not a valid identifier"));
+        }
+        shell.vars_mut().unset(arg);
     }
-    Ok(())
+    Ok(0)
 }
 
-fn type_cmd(args: &[String], _shell: &mut Shell) -> Result<(), String> {
-    let dispatch_table = build_dispatch_table();
-    if let Some(arg) = args.first() {
-        if arg.chars().all(char::is_whitespace) {
-            println!();
-            return Ok(());
+fn local(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    if shell.function_depth() == 0 {
+        return Err("local: can only be used in a function".to_string());
+    }
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !is_valid_name(name) {
+            return Err(format!("local: `{arg}': not a valid identifier"));
         }
-        if dispatch_table.contains_key(arg) {
-            println!("{arg} : BUILTIN");
-            return Ok(());
+        shell.vars_mut().make_local(name);
+        match value {
+            Some(value) => shell.vars_mut().set(name, value.to_string()),
+            None => shell.vars_mut().unset(name),
         }
-        if external_command_exists(arg) {
-            println!("{arg} : EXTERNAL");
-            return Ok(());
-        } else {
-            println!("{arg} : UNKNOW COMMAND");
-            return Ok(());
+    }
+    Ok(0)
+}
+
+fn return_cmd(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    if shell.function_depth() == 0 && shell.source_depth() == 0 {
+        return Err("return: can only `return' from a function or sourced script".to_string());
+    }
+    let status = match args {
+        [] => shell.last_status(),
+        [arg] => {
+            arg.parse::<i32>()
+                .map_err(|_| format!("return: {arg}: numeric argument required"))?
+                & 0xff
+        }
+        _ => return Err("return: too many arguments".to_string()),
+    };
+    shell.set_control(Some(Control::Return(status)));
+    Ok(status)
+}
+
+fn let_cmd(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    if args.is_empty() {
+        return Err("let: expression expected".to_string());
+    }
+    let mut value = 0;
+    for arg in args {
+        value = evaluate(arg, shell).map_err(|e| format!("let: {e}"))?;
+    }
+    Ok(if value == 0 { 1 } else { 0 })
+}
+
+fn shopt(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    let mut value = None;
+    let mut quiet = false;
+    let mut names = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-s" => value = Some(true),
+            "-u" => value = Some(false),
+            "-q" => quiet = true,
+            _ if arg.starts_with('-') => return Err(format!("shopt: {arg}: invalid option")),
+            _ => names.push(arg.as_str()),
+        }
+    }
+    if let Some(value) = value {
+        for name in names {
+            shell
+                .options_mut()
+                .set(name, value)
+                .map_err(|e| format!("shopt: {e}"))?;
+        }
+        return Ok(0);
+    }
+    if names.is_empty() {
+        names = ShellOptions::NAMES.to_vec();
+    }
+    let mut status = 0;
+    let mut out = shell.stdout();
+    for name in names {
+        let on = shell
+            .options()
+            .get(name)
+            .ok_or_else(|| format!("shopt: {name}: invalid shell option name"))?;
+        if !on {
+            status = 1;
+        }
+        if !quiet {
+            let state = if on { "on" } else { "off" };
+            writeln!(out, "{name}\t{state}").map_err(|e| write_error("shopt", e))?;
+        }
+    }
+    Ok(status)
+}
+
+fn colon(_args: &[String], _shell: &mut Shell) -> Result<i32, String> {
+    Ok(0)
+}
+
+// `.` and `source`: runs a file in the current shell, with any further
+// arguments as its positional parameters.
+fn source(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    let Some((file, params)) = args.split_first() else {
+        return Err("source: filename argument required".to_string());
+    };
+    let path = find_source(file, shell);
+    let saved = (!params.is_empty()).then(|| shell.set_positional(params.to_vec()));
+    let result = shell.source_file(&path).map_err(|e| format!("source: {e}"));
+    if let Some(saved) = saved {
+        shell.set_positional(saved);
+    }
+    result
+}
+
+// A name without a slash is looked up in `PATH`, then in the current directory.
+fn find_source(file: &str, shell: &Shell) -> String {
+    if !file.contains('/')
+        && let Some(path) = shell.vars().get("PATH")
+    {
+        for dir in env::split_paths(path) {
+            let candidate = dir.join(file);
+            if candidate.is_file() {
+                return candidate.display().to_string();
+            }
+        }
+    }
+    file.to_string()
+}
+
+// Sets `-e` and `-x`, by letter or with `-o name`, and the positional
+// parameters from the first operand or everything after `--`.
+fn set(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    if args.is_empty() {
+        let mut vars: Vec<(&str, &str)> = shell.vars().iter().collect();
+        vars.sort();
+        let mut out = shell.stdout();
+        for (name, value) in vars {
+            writeln!(out, "{name}={}", quote(value)).map_err(|e| write_error("set", e))?;
+        }
+        return Ok(0);
+    }
+    let mut operands = false;
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        i += 1;
+        let on = match arg.as_str() {
+            "--" => {
+                operands = true;
+                break;
+            }
+            "-" => {
+                operands = i < args.len();
+                break;
+            }
+            _ if arg.starts_with('-') => true,
+            _ if arg.starts_with('+') => false,
+            _ => {
+                i -= 1;
+                operands = true;
+                break;
+            }
+        };
+        for c in arg.chars().skip(1) {
+            match c {
+                'e' => shell.set_errexit(on),
+                'x' => shell.set_xtrace(on),
+                'o' => match args.get(i) {
+                    Some(name) => {
+                        i += 1;
+                        set_option(name, on, shell)?;
+                    }
+                    None => print_set_options(shell)?,
+                },
+                _ => return Err(format!("set: {}{c}: invalid option", &arg[..1])),
+            }
         }
     }
+    if operands {
+        shell.set_positional(args[i..].to_vec());
+    }
+    Ok(0)
+}
+
+fn set_option(name: &str, on: bool, shell: &mut Shell) -> Result<(), String> {
+    match name {
+        "errexit" => shell.set_errexit(on),
+        "xtrace" => shell.set_xtrace(on),
+        _ => return Err(format!("set: {name}: invalid option name")),
+    }
+    Ok(())
+}
+
+fn print_set_options(shell: &Shell) -> Result<(), String> {
+    let mut out = shell.stdout();
+    for (name, on) in [("errexit", shell.errexit()), ("xtrace", shell.xtrace())] {
+        let state = if on { "on" } else { "off" };
+        writeln!(out, "{name}\t{state}").map_err(|e| write_error("set", e))?;
+    }
     Ok(())
 }
 
+fn break_cmd(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    let count = loop_count("break", args, shell)?;
+    shell.set_control(Some(Control::Break(count)));
+    Ok(0)
+}
+
+fn continue_cmd(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    let count = loop_count("continue", args, shell)?;
+    shell.set_control(Some(Control::Continue(count)));
+    Ok(0)
+}
+
+// The number of enclosing loops that `break` or `continue` applies to.
+fn loop_count(name: &str, args: &[String], shell: &Shell) -> Result<usize, String> {
+    if shell.loop_depth() == 0 {
+        return Err(format!(
+            "{name}: only meaningful in a `for', `while', or `until' loop"
+        ));
+    }
+    let count = match args {
+        [] => 1,
+        [arg] => match arg.parse::<i64>() {
+            Ok(n) if n >= 1 => n as usize,
+            Ok(_) => return Err(format!("{name}: {arg}: loop count out of range")),
+            Err(_) => return Err(format!("{name}: {arg}: numeric argument required")),
+        },
+        _ => return Err(format!("{name}: too many arguments")),
+    };
+    Ok(count.min(shell.loop_depth()))
+}
+
 pub fn build_dispatch_table() -> HashMap<String, CmdFn> {
     let mut map: HashMap<String, CmdFn> = HashMap::new();
 
@@ -94,26 +384,39 @@ pub fn build_dispatch_table() -> HashMap<String, CmdFn> {
     map.insert("cd".to_string(), Box::new(cd));
     map.insert("history".to_string(), Box::new(history_cmd));
     map.insert("type".to_string(), Box::new(type_cmd));
+    map.insert("export".to_string(), Box::new(export));
+    map.insert("unset".to_string(), Box::new(unset));
+    map.insert("let".to_string(), Box::new(let_cmd));
+    map.insert("shopt".to_string(), Box::new(shopt));
+    map.insert("break".to_string(), Box::new(break_cmd));
+    map.insert("continue".to_string(), Box::new(continue_cmd));
+    map.insert("local".to_string(), Box::new(local));
+    map.insert("return".to_string(), Box::new(return_cmd));
+    map.insert(":".to_string(), Box::new(colon));
+    map.insert(".".to_string(), Box::new(source));
+    map.insert("source".to_string(), Box::new(source));
+    map.insert("set".to_string(), Box::new(set));
 
     map
 }
 
 pub fn check_builtin_existance(name: &str) -> bool {
-    let builtins: Vec<&str> = vec!["echo", "exit", "pwd", "cd", "history", "type"];
+    let builtins: Vec<&str> = vec![
+        "echo", "exit", "pwd", "cd", "history", "type", "export", "unset", "let", "shopt", "break",
+        "continue", "local", "return", ":", ".", "source", "set",
+    ];
     builtins.contains(&name)
 }
 
 impl<'a> Execute for BuiltinCommand<'a> {
-    fn execute(&self, args: &[String], shell: &mut Shell) -> Result<(), String> {
+    fn execute(&self, args: &[String], shell: &mut Shell) -> Result<i32, String> {
         let dispatch_table = build_dispatch_table();
         if let Some(func) = dispatch_table.get(self.name) {
-            let result = func(args, shell);
-            if result.is_ok() {
-                shell
-                    .history_mut()
-                    .push(format!("{} {}", self.name, args.join(" ")));
-            }
-            result
+            let status = func(args, shell)?;
+            shell
+                .history_mut()
+                .push(format!("{} {}", self.name, args.join(" ")));
+            Ok(status)
         } else {
             Err(format!("Erreur Executing Command: {}", self.name))
         }
@@ -145,7 +448,19 @@ mod tests {
         assert!(table.contains_key("cd"));
         assert!(table.contains_key("history"));
         assert!(table.contains_key("type"));
-        assert_eq!(table.len(), 6);
+        assert!(table.contains_key("export"));
+        assert!(table.contains_key("unset"));
+        assert!(table.contains_key("let"));
+        assert!(table.contains_key("shopt"));
+        assert!(table.contains_key("break"));
+        assert!(table.contains_key("continue"));
+        assert!(table.contains_key("local"));
+        assert!(table.contains_key("return"));
+        assert!(table.contains_key(":"));
+        assert!(table.contains_key("."));
+        assert!(table.contains_key("source"));
+        assert!(table.contains_key("set"));
+        assert_eq!(table.len(), 18);
     }
 
     #[test]
@@ -267,6 +582,8 @@ mod tests {
         let args = vec![".".to_string()];
         let result = cd(&args, &mut shell);
         assert!(result.is_ok());
+        assert!(shell.vars().get("PWD").is_some());
+        assert!(shell.vars().get("OLDPWD").is_some());
     }
 
     #[test]
@@ -284,4 +601,166 @@ mod tests {
         let result = history_cmd(&args, &mut shell);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_export_and_unset() {
+        let mut shell = Shell::new();
+        let args = vec!["NEW_VAR=1".to_string(), "OTHER_VAR".to_string()];
+        assert!(export(&args, &mut shell).is_ok());
+        assert_eq!(shell.vars().get("NEW_VAR"), Some("1"));
+        assert!(shell.vars().get_var("OTHER_VAR").unwrap().exported);
+
+        let args = vec!["NEW_VAR".to_string()];
+        assert!(unset(&args, &mut shell).is_ok());
+        assert_eq!(shell.vars().get("NEW_VAR"), None);
+    }
+
+    #[test]
+    fn test_export_invalid_name() {
+        let mut shell = Shell::new();
+        let args = vec!["1BAD=x".to_string()];
+        let result = export(&args, &mut shell);
+        assert_eq!(
+            result,
+            Err("export: `1BAD=x': not a valid identifier".to_string())
+        );
+    }
+
+    #[test]
+    fn test_let() {
+        let mut shell = Shell::new();
+        let args = vec!["x = 2 + 3".to_string(), "y = x * 2".to_string()];
+        assert_eq!(let_cmd(&args, &mut shell), Ok(0));
+        assert_eq!(shell.vars().get("y"), Some("10"));
+        assert_eq!(let_cmd(&["x - 5".to_string()], &mut shell), Ok(1));
+        assert_eq!(
+            let_cmd(&["1/0".to_string()], &mut shell),
+            Err("let: 1/0: division by 0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_shopt() {
+        let mut shell = Shell::new();
+        let query = |shell: &mut Shell| shopt(&["-q".to_string(), "nullglob".to_string()], shell);
+        assert_eq!(query(&mut shell), Ok(1));
+        assert_eq!(
+            shopt(&["-s".to_string(), "nullglob".to_string()], &mut shell),
+            Ok(0)
+        );
+        assert!(shell.options().nullglob);
+        assert_eq!(query(&mut shell), Ok(0));
+        assert_eq!(
+            shopt(&["-u".to_string(), "bogus".to_string()], &mut shell),
+            Err("shopt: bogus: invalid shell option name".to_string())
+        );
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            break_cmd(&[], &mut shell),
+            Err("break: only meaningful in a `for', `while', or `until' loop".to_string())
+        );
+        shell.enter_loop();
+        shell.enter_loop();
+        assert_eq!(break_cmd(&["5".to_string()], &mut shell), Ok(0));
+        assert_eq!(shell.take_control(), Some(Control::Break(2)));
+        assert_eq!(continue_cmd(&[], &mut shell), Ok(0));
+        assert_eq!(shell.take_control(), Some(Control::Continue(1)));
+        assert_eq!(
+            continue_cmd(&["0".to_string()], &mut shell),
+            Err("continue: 0: loop count out of range".to_string())
+        );
+    }
+
+    #[test]
+    fn test_local_and_return_outside_function() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            local(&["x=1".to_string()], &mut shell),
+            Err("local: can only be used in a function".to_string())
+        );
+        assert_eq!(
+            return_cmd(&[], &mut shell),
+            Err("return: can only `return' from a function or sourced script".to_string())
+        );
+    }
+
+    #[test]
+    fn test_return() {
+        let mut shell = Shell::new();
+        shell.enter_function();
+        shell.set_last_status(3);
+        assert_eq!(return_cmd(&[], &mut shell), Ok(3));
+        assert_eq!(shell.take_control(), Some(Control::Return(3)));
+        assert_eq!(return_cmd(&["257".to_string()], &mut shell), Ok(1));
+        assert_eq!(shell.take_control(), Some(Control::Return(1)));
+        assert_eq!(
+            return_cmd(&["x".to_string()], &mut shell),
+            Err("return: x: numeric argument required".to_string())
+        );
+    }
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut shell = Shell::new();
+        shell.set_last_status(3);
+        assert_eq!(exit(&[], &mut shell), Ok(3));
+        assert_eq!(shell.take_control(), Some(Control::Exit(3)));
+        assert_eq!(exit(&strings(&["257"]), &mut shell), Ok(1));
+        assert_eq!(shell.take_control(), Some(Control::Exit(1)));
+        assert_eq!(
+            exit(&strings(&["x"]), &mut shell),
+            Err("exit: x: numeric argument required".to_string())
+        );
+        assert_eq!(shell.control(), None);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut shell = Shell::new();
+        assert_eq!(colon(&strings(&["x"]), &mut shell), Ok(0));
+        assert_eq!(set(&strings(&["-ex"]), &mut shell), Ok(0));
+        assert!(shell.errexit() && shell.xtrace());
+        assert_eq!(set(&strings(&["+o", "errexit", "+x"]), &mut shell), Ok(0));
+        assert!(!shell.errexit() && !shell.xtrace());
+        assert_eq!(
+            set(&strings(&["-o", "xtrace", "a", "-b"]), &mut shell),
+            Ok(0)
+        );
+        assert!(shell.xtrace());
+        assert_eq!(shell.positional(), strings(&["a", "-b"]));
+        assert_eq!(set(&strings(&["--", "-e"]), &mut shell), Ok(0));
+        assert!(!shell.errexit());
+        assert_eq!(shell.positional(), strings(&["-e"]));
+        assert_eq!(set(&strings(&["--"]), &mut shell), Ok(0));
+        assert!(shell.positional().is_empty());
+        assert_eq!(
+            set(&strings(&["-q"]), &mut shell),
+            Err("set: -q: invalid option".to_string())
+        );
+        assert_eq!(
+            set(&strings(&["+o", "bogus"]), &mut shell),
+            Err("set: bogus: invalid option name".to_string())
+        );
+    }
+
+    #[test]
+    fn test_source_errors() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            source(&[], &mut shell),
+            Err("source: filename argument required".to_string())
+        );
+        assert_eq!(
+            source(&strings(&["/nonexistent/file"]), &mut shell),
+            Err("source: /nonexistent/file: No such file or directory".to_string())
+        );
+    }
 }
@@ -0,0 +1,209 @@
+//! The command line of the shell binary.
+
+pub const USAGE: &str = "\
+Usage: shell [option ...] [script [arg ...]]
+       shell [option ...] -c command [name [arg ...]]
+       shell [option ...] -s [arg ...]
+
+Options:
+  -c              run the command string given as the first argument
+  -s              read commands from standard input
+  -i              run interactively
+  -l, --login     act as a login shell and read the profile files
+  -e              exit as soon as a command fails
+  -x              print each command before running it
+  --norc          do not read ~/.shellrc in an interactive shell
+  --rcfile FILE   read FILE instead of ~/.shellrc
+  --version       print the version and exit
+  --help          print this help and exit
+";
+
+/// Where the commands to run come from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mode {
+    Command(String),
+    Script(String),
+    Stdin,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub mode: Mode,
+    /// `$0`: the script, the name given after a `-c` command, or the shell itself.
+    pub name: String,
+    pub args: Vec<String>,
+    /// Set by `-i`; a shell reading a terminal is interactive without it.
+    pub interactive: bool,
+    pub login: bool,
+    pub errexit: bool,
+    pub xtrace: bool,
+    pub norc: bool,
+    pub rcfile: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Run(Invocation),
+    Help,
+    Version,
+}
+
+/// Parses the full argument list, program name included. Options end at the
+/// first operand, `-` or `--`, as in other POSIX shells.
+pub fn parse_args(argv: &[String]) -> Result<Action, String> {
+    let program = argv.first().map_or("shell", String::as_str);
+    let mut invocation = Invocation {
+        mode: Mode::Stdin,
+        name: program.to_string(),
+        args: Vec::new(),
+        interactive: false,
+        // A leading `-` in the program name is how login(1) starts a login shell.
+        login: program.starts_with('-'),
+        errexit: false,
+        xtrace: false,
+        norc: false,
+        rcfile: None,
+    };
+    let (mut command, mut stdin) = (false, false);
+    let mut i = 1;
+    while let Some(arg) = argv.get(i) {
+        i += 1;
+        match arg.as_str() {
+            "-" | "--" => break,
+            "--help" => return Ok(Action::Help),
+            "--version" => return Ok(Action::Version),
+            "--login" => invocation.login = true,
+            "--norc" => invocation.norc = true,
+            "--rcfile" => {
+                let file = argv.get(i).ok_or("--rcfile: option requires an argument")?;
+                invocation.rcfile = Some(file.clone());
+                i += 1;
+            }
+            _ if arg.starts_with("--") => return Err(format!("{arg}: invalid option")),
+            _ if arg.starts_with('-') => {
+                for c in arg.chars().skip(1) {
+                    match c {
+                        'c' => command = true,
+                        's' => stdin = true,
+                        'i' => invocation.interactive = true,
+                        'l' => invocation.login = true,
+                        'e' => invocation.errexit = true,
+                        'x' => invocation.xtrace = true,
+                        _ => return Err(format!("-{c}: invalid option")),
+                    }
+                }
+            }
+            _ => {
+                i -= 1;
+                break;
+            }
+        }
+    }
+    let operands = &argv[i..];
+    if command {
+        let (source, rest) = operands
+            .split_first()
+            .ok_or("-c: option requires an argument")?;
+        invocation.mode = Mode::Command(source.clone());
+        if let Some((name, args)) = rest.split_first() {
+            invocation.name = name.clone();
+            invocation.args = args.to_vec();
+        }
+    } else if stdin || operands.is_empty() {
+        invocation.args = operands.to_vec();
+    } else {
+        invocation.mode = Mode::Script(operands[0].clone());
+        invocation.name = operands[0].clone();
+        invocation.args = operands[1..].to_vec();
+    }
+    Ok(Action::Run(invocation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Action, String> {
+        let argv: Vec<String> = ["shell"]
+            .iter()
+            .chain(args)
+            .map(|a| a.to_string())
+            .collect();
+        parse_args(&argv)
+    }
+
+    fn invocation(args: &[&str]) -> Invocation {
+        match parse(args) {
+            Ok(Action::Run(invocation)) => invocation,
+            other => panic!("{args:?} gave {other:?}"),
+        }
+    }
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args_modes() {
+        let stdin = invocation(&[]);
+        assert_eq!(stdin.mode, Mode::Stdin);
+        assert_eq!(stdin.name, "shell");
+        let script = invocation(&["deploy.sh", "prod", "-x"]);
+        assert_eq!(script.mode, Mode::Script("deploy.sh".to_string()));
+        assert_eq!(script.name, "deploy.sh");
+        assert_eq!(script.args, strings(&["prod", "-x"]));
+        assert!(!script.xtrace);
+        let stdin = invocation(&["-s", "a", "b"]);
+        assert_eq!(stdin.mode, Mode::Stdin);
+        assert_eq!(stdin.args, strings(&["a", "b"]));
+        assert_eq!(
+            invocation(&["--", "-x"]).mode,
+            Mode::Script("-x".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_args_command() {
+        let command = invocation(&["-c", "echo $0 $1", "name", "arg"]);
+        assert_eq!(command.mode, Mode::Command("echo $0 $1".to_string()));
+        assert_eq!(command.name, "name");
+        assert_eq!(command.args, strings(&["arg"]));
+        let command = invocation(&["-ec", "false"]);
+        assert_eq!(command.mode, Mode::Command("false".to_string()));
+        assert_eq!(command.name, "shell");
+        assert!(command.errexit);
+        let command = invocation(&["-c", "-x", "true"]);
+        assert_eq!(command.mode, Mode::Command("true".to_string()));
+        assert!(command.xtrace);
+    }
+
+    #[test]
+    fn test_parse_args_options() {
+        let options = invocation(&["-il", "--norc", "--rcfile", "rc", "-ex"]);
+        assert!(options.interactive && options.login && options.norc);
+        assert!(options.errexit && options.xtrace);
+        assert_eq!(options.rcfile, Some("rc".to_string()));
+        assert!(invocation(&["--login"]).login);
+        let argv = strings(&["-shell"]);
+        assert!(matches!(parse_args(&argv), Ok(Action::Run(inv)) if inv.login));
+        assert_eq!(parse(&["--help", "-q"]), Ok(Action::Help));
+        assert_eq!(parse(&["--version"]), Ok(Action::Version));
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert_eq!(parse(&["-q"]), Err("-q: invalid option".to_string()));
+        assert_eq!(
+            parse(&["--bogus"]),
+            Err("--bogus: invalid option".to_string())
+        );
+        assert_eq!(
+            parse(&["-c"]),
+            Err("-c: option requires an argument".to_string())
+        );
+        assert_eq!(
+            parse(&["--rcfile"]),
+            Err("--rcfile: option requires an argument".to_string())
+        );
+    }
+}
@@ -1,12 +1,13 @@
 use crate::builtin::{BuiltinCommand, check_builtin_existance};
-use crate::external::NonBuiltinCommand;
+use crate::exec::call_function;
+use crate::external::{COMMAND_NOT_FOUND, NonBuiltinCommand};
 use crate::shell::Shell;
 
 pub trait Execute {
-    fn execute(&self, args: &[String], shell: &mut Shell) -> Result<(), String>;
+    fn execute(&self, args: &[String], shell: &mut Shell) -> Result<i32, String>;
 }
 
-pub type CmdFn = Box<dyn Fn(&[String], &mut Shell) -> Result<(), String>>;
+pub type CmdFn = Box<dyn Fn(&[String], &mut Shell) -> Result<i32, String>>;
 
 pub struct Cmd<'a> {
     name: &'a str,
@@ -19,14 +20,20 @@ impl<'a> Cmd<'a> {
 }
 
 impl<'a> Execute for Cmd<'a> {
-    fn execute(&self, args: &[String], shell: &mut Shell) -> Result<(), String> {
-        if check_builtin_existance(self.name) {
+    fn execute(&self, args: &[String], shell: &mut Shell) -> Result<i32, String> {
+        if let Some(body) = shell.function(self.name) {
+            call_function(&body, args, shell)
+        } else if check_builtin_existance(self.name) {
             let cmd = BuiltinCommand::new(self.name)?;
-            cmd.execute(args, shell)?;
+            cmd.execute(args, shell)
         } else {
-            let cmd = NonBuiltinCommand::new(self.name)?;
-            cmd.execute(args, shell)?;
+            match NonBuiltinCommand::new(self.name, shell) {
+                Ok(cmd) => cmd.execute(args, shell),
+                Err(e) => {
+                    shell.report_error(&e);
+                    Ok(COMMAND_NOT_FOUND)
+                }
+            }
         }
-        Ok(())
     }
 }
@@ -0,0 +1,1061 @@
+use crate::arith;
+use crate::ast::{
+    AndOr, Assignment, CaseItem, CaseTerminator, Command, CompoundCommand, Connector, List,
+    Pipeline, SimpleCommand,
+};
+use crate::cmd::{Cmd, Execute};
+use crate::expand::{expand_assignment, expand_pattern, expand_string, expand_word};
+use crate::fds::{STDERR, STDIN, STDOUT};
+use crate::redirect::with_redirects;
+use crate::shell::{Control, Shell};
+use crate::sys::{self, Pid, error_message};
+use crate::vars::Variable;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+pub trait Run {
+    fn run(&self, shell: &mut Shell) -> Result<i32, String>;
+}
+
+// Errors that stop a command from running are reported and turned into a
+// failing status, so they never escape the pipeline that caused them.
+fn recover(result: Result<i32, String>, shell: &Shell) -> i32 {
+    result.unwrap_or_else(|e| {
+        shell.report_error(&e);
+        1
+    })
+}
+
+impl Run for List {
+    fn run(&self, shell: &mut Shell) -> Result<i32, String> {
+        let mut status = 0;
+        for item in &self.items {
+            status = if item.background {
+                let err = "background jobs are not supported yet".to_string();
+                recover(Err(err), shell)
+            } else {
+                item.and_or.run(shell)?
+            };
+            if shell.control().is_some() {
+                break;
+            }
+        }
+        Ok(status)
+    }
+}
+
+impl Run for AndOr {
+    fn run(&self, shell: &mut Shell) -> Result<i32, String> {
+        // Every pipeline but the last is tested by the next `&&` or `||`.
+        let mut status = if self.rest.is_empty() {
+            self.first.run(shell)?
+        } else {
+            run_condition(shell, |shell| self.first.run(shell))?
+        };
+        let mut ran_last = self.rest.is_empty();
+        for (i, (connector, pipeline)) in self.rest.iter().enumerate() {
+            if shell.control().is_some() {
+                break;
+            }
+            let proceed = match connector {
+                Connector::And => status == 0,
+                Connector::Or => status != 0,
+            };
+            if !proceed {
+                continue;
+            }
+            ran_last = i + 1 == self.rest.len();
+            status = if ran_last {
+                pipeline.run(shell)?
+            } else {
+                run_condition(shell, |shell| pipeline.run(shell))?
+            };
+        }
+        let negated = self
+            .rest
+            .last()
+            .map_or(&self.first, |(_, last)| last)
+            .negated;
+        if status != 0
+            && ran_last
+            && !negated
+            && shell.errexit()
+            && shell.condition_depth() == 0
+            && shell.control().is_none()
+        {
+            shell.set_control(Some(Control::Exit(status)));
+        }
+        Ok(status)
+    }
+}
+
+// Runs a command whose status is tested, so that its failure does not make
+// `-e` exit the shell.
+fn run_condition(
+    shell: &mut Shell,
+    run: impl FnOnce(&mut Shell) -> Result<i32, String>,
+) -> Result<i32, String> {
+    shell.enter_condition();
+    let result = run(shell);
+    shell.leave_condition();
+    result
+}
+
+impl Run for Pipeline {
+    fn run(&self, shell: &mut Shell) -> Result<i32, String> {
+        let run = |shell: &mut Shell| match self.commands.as_slice() {
+            [command] => command.run(shell),
+            commands => run_pipeline(commands, shell),
+        };
+        let result = if self.negated {
+            run_condition(shell, run)
+        } else {
+            run(shell)
+        };
+        let mut status = recover(result, shell);
+        if self.negated {
+            status = if status == 0 { 1 } else { 0 };
+        }
+        shell.set_last_status(status);
+        Ok(status)
+    }
+}
+
+// Every stage runs in its own forked child so that builtins and compound
+// commands stream through the pipes concurrently, just like external ones.
+fn run_pipeline(commands: &[Command], shell: &mut Shell) -> Result<i32, String> {
+    let mut pids = Vec::new();
+    let result = spawn_stages(commands, shell, &mut pids);
+    let mut status = 0;
+    for pid in pids {
+        let exit = sys::wait(pid).map_err(|e| format!("wait: {e}"))?;
+        status = shell.foreground_status(exit);
+    }
+    result.map(|()| status)
+}
+
+fn spawn_stages(
+    commands: &[Command],
+    shell: &mut Shell,
+    pids: &mut Vec<Pid>,
+) -> Result<(), String> {
+    let mut stdin = None;
+    for (i, command) in commands.iter().enumerate() {
+        let (next_stdin, stdout) = if i + 1 < commands.len() {
+            let (read, write) = sys::pipe().map_err(|e| format!("pipe: {e}"))?;
+            (Some(read), Some(write))
+        } else {
+            (None, None)
+        };
+        match sys::fork().map_err(|e| format!("fork: {e}"))? {
+            None => {
+                drop(next_stdin);
+                if let Some(fd) = stdin.take() {
+                    shell.fds_mut().set(STDIN, Rc::new(fd));
+                }
+                if let Some(fd) = stdout {
+                    shell.fds_mut().set(STDOUT, Rc::new(fd));
+                }
+                run_in_child(shell, |shell| command.run(shell));
+            }
+            Some(pid) => pids.push(pid),
+        }
+        stdin = next_stdin;
+    }
+    Ok(())
+}
+
+/// Runs `list` in a forked child, returning everything it wrote to stdout and its status.
+pub fn capture_output(list: &List, shell: &mut Shell) -> Result<(Vec<u8>, i32), String> {
+    let (read, write) = sys::pipe().map_err(|e| format!("pipe: {e}"))?;
+    let pid = match sys::fork().map_err(|e| format!("fork: {e}"))? {
+        None => {
+            drop(read);
+            shell.fds_mut().set(STDOUT, Rc::new(write));
+            run_in_child(shell, |shell| list.run(shell));
+        }
+        Some(pid) => pid,
+    };
+    drop(write);
+    // Read everything before waiting, so a child with a lot of output never
+    // blocks on a full pipe.
+    let mut output = Vec::new();
+    let result = File::from(read).read_to_end(&mut output);
+    let status = sys::wait(pid).map_err(|e| format!("wait: {e}"))?;
+    result.map_err(|e| format!("command substitution: {}", error_message(&e)))?;
+    Ok((output, shell.foreground_status(status)))
+}
+
+// Runs `list` in a forked child, so that its `cd`, assignments, options and
+// functions stay out of the shell.
+fn run_subshell(list: &List, shell: &mut Shell) -> Result<i32, String> {
+    let pid = match sys::fork().map_err(|e| format!("fork: {e}"))? {
+        None => run_in_child(shell, |shell| list.run(shell)),
+        Some(pid) => pid,
+    };
+    let status = sys::wait(pid).map_err(|e| format!("wait: {e}"))?;
+    Ok(shell.foreground_status(status))
+}
+
+fn run_in_child(shell: &mut Shell, f: impl FnOnce(&mut Shell) -> Result<i32, String>) -> ! {
+    sys::reset_child_signals();
+    let code = match panic::catch_unwind(AssertUnwindSafe(|| f(shell))) {
+        Ok(Ok(_)) if let Some(Control::Exit(status) | Control::Abort(status)) = shell.control() => {
+            status
+        }
+        Ok(Ok(status)) => status,
+        Ok(Err(e)) => {
+            shell.report_error(&e);
+            1
+        }
+        Err(_) => 1,
+    };
+    sys::exit_child(code)
+}
+
+/// Runs `list` in a forked child on one end of a pipe and returns the
+/// `/dev/fd` path of the other end: the child's output for `<(list)` and its
+/// input for `>(list)`. The pipe stays open until the current command ends.
+pub fn process_substitution(list: &List, input: bool, shell: &mut Shell) -> Result<String, String> {
+    let (read, write) = sys::pipe().map_err(|e| format!("pipe: {e}"))?;
+    let (ours, theirs, fd) = if input {
+        (read, write, STDOUT)
+    } else {
+        (write, read, STDIN)
+    };
+    sys::set_inheritable(&ours).map_err(|e| format!("process substitution: {e}"))?;
+    let pid = match sys::fork().map_err(|e| format!("fork: {e}"))? {
+        None => {
+            drop(ours);
+            shell.forget_process_substitutions();
+            shell.fds_mut().set(fd, Rc::new(theirs));
+            run_in_child(shell, |shell| list.run(shell));
+        }
+        Some(pid) => pid,
+    };
+    drop(theirs);
+    let path = format!("/dev/fd/{}", ours.as_raw_fd());
+    shell.add_process_substitution(ours, pid);
+    Ok(path)
+}
+
+impl Run for Command {
+    fn run(&self, shell: &mut Shell) -> Result<i32, String> {
+        let keep = shell.process_substitution_count();
+        let result = match self {
+            Command::Simple(simple) => simple.run(shell),
+            Command::Compound(compound, redirects) => {
+                with_redirects(redirects, shell, |shell| compound.run(shell))
+            }
+            Command::FunctionDefinition { name, body } => {
+                shell.define_function(name, body.clone());
+                Ok(0)
+            }
+        };
+        shell.reap_process_substitutions(keep);
+        result
+    }
+}
+
+impl Run for CompoundCommand {
+    fn run(&self, shell: &mut Shell) -> Result<i32, String> {
+        match self {
+            CompoundCommand::BraceGroup(list) => list.run(shell),
+            CompoundCommand::Subshell(list) => run_subshell(list, shell),
+            CompoundCommand::Arithmetic(expr) => {
+                let expr = expand_string(expr, shell)?;
+                let value = arith::evaluate(&expr, shell)?;
+                Ok(if value == 0 { 1 } else { 0 })
+            }
+            CompoundCommand::If {
+                branches,
+                else_body,
+            } => {
+                for (condition, body) in branches {
+                    let status = run_condition(shell, |shell| condition.run(shell))?;
+                    // The condition may have run `exit`, `return` or `break`.
+                    if shell.control().is_some() {
+                        return Ok(status);
+                    }
+                    if status == 0 {
+                        return body.run(shell);
+                    }
+                }
+                match else_body {
+                    Some(body) => body.run(shell),
+                    None => Ok(0),
+                }
+            }
+            CompoundCommand::For { name, words, body } => {
+                let mut values = Vec::new();
+                match words {
+                    Some(words) => {
+                        for word in words {
+                            values.extend(expand_word(word, shell)?);
+                        }
+                    }
+                    None => values.extend_from_slice(shell.positional()),
+                }
+                let mut values = values.into_iter();
+                run_loop(body, shell, |shell| {
+                    let Some(value) = values.next() else {
+                        return Ok(false);
+                    };
+                    shell.vars_mut().set(name, value);
+                    Ok(true)
+                })
+            }
+            CompoundCommand::ArithmeticFor {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                evaluate_if_present(init, shell)?;
+                let mut first = true;
+                run_loop(body, shell, |shell| {
+                    if !mem::take(&mut first) {
+                        evaluate_if_present(update, shell)?;
+                    }
+                    Ok(evaluate_if_present(condition, shell)?.unwrap_or(1) != 0)
+                })
+            }
+            CompoundCommand::While {
+                condition,
+                body,
+                until,
+            } => run_loop(body, shell, |shell| {
+                let status = run_condition(shell, |shell| condition.run(shell))?;
+                Ok((status == 0) != *until)
+            }),
+            CompoundCommand::Case { word, items } => run_case(word, items, shell),
+        }
+    }
+}
+
+fn run_case(word: &str, items: &[CaseItem], shell: &mut Shell) -> Result<i32, String> {
+    let word = expand_string(word, shell)?;
+    let mut status = 0;
+    let mut fall_through = false;
+    for item in items {
+        if !fall_through && !case_matches(&word, &item.patterns, shell)? {
+            continue;
+        }
+        status = item.body.run(shell)?;
+        if shell.control().is_some() {
+            break;
+        }
+        match item.terminator {
+            CaseTerminator::Break => break,
+            CaseTerminator::FallThrough => fall_through = true,
+            CaseTerminator::Continue => fall_through = false,
+        }
+    }
+    Ok(status)
+}
+
+fn case_matches(word: &str, patterns: &[String], shell: &mut Shell) -> Result<bool, String> {
+    for pattern in patterns {
+        if expand_pattern(pattern, shell)?.matches(word) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Runs `body` for as long as `next` prepares another pass. The status is that
+// of the last pass, or 0 if there was none.
+fn run_loop(
+    body: &List,
+    shell: &mut Shell,
+    mut next: impl FnMut(&mut Shell) -> Result<bool, String>,
+) -> Result<i32, String> {
+    shell.enter_loop();
+    let mut result = Ok(0);
+    loop {
+        match next(shell) {
+            // The header itself may have run `break` or `continue`.
+            Ok(more) if loop_continues(shell) && more => {}
+            Ok(_) => break,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+        result = body.run(shell);
+        if result.is_err() || !loop_continues(shell) {
+            break;
+        }
+    }
+    shell.leave_loop();
+    result
+}
+
+// Takes a pending `break` or `continue` aimed at the innermost loop, handing
+// what is left of a deeper one on to the enclosing loop.
+fn loop_continues(shell: &mut Shell) -> bool {
+    match shell.take_control() {
+        None | Some(Control::Continue(1)) => true,
+        Some(Control::Break(1)) => false,
+        Some(Control::Break(n)) => {
+            shell.set_control(Some(Control::Break(n - 1)));
+            false
+        }
+        Some(Control::Continue(n)) => {
+            shell.set_control(Some(Control::Continue(n - 1)));
+            false
+        }
+        Some(control) => {
+            shell.set_control(Some(control));
+            false
+        }
+    }
+}
+
+/// Runs a function body with `args` as its positional parameters, in a new
+/// scope for its `local` variables.
+pub fn call_function(body: &Command, args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    let saved = shell.set_positional(args.to_vec());
+    shell.vars_mut().push_scope();
+    shell.enter_function();
+    let result = body.run(shell);
+    shell.leave_function();
+    shell.vars_mut().pop_scope();
+    shell.set_positional(saved);
+    match shell.control() {
+        Some(Control::Return(status)) => {
+            shell.take_control();
+            Ok(status)
+        }
+        _ => result,
+    }
+}
+
+// Evaluates one expression of a `for ((...))` header, where each may be empty.
+fn evaluate_if_present(expr: &str, shell: &mut Shell) -> Result<Option<i64>, String> {
+    if expr.trim().is_empty() {
+        return Ok(None);
+    }
+    let expr = expand_string(expr, shell)?;
+    arith::evaluate(&expr, shell).map(Some)
+}
+
+impl Run for SimpleCommand {
+    fn run(&self, shell: &mut Shell) -> Result<i32, String> {
+        shell.take_substitution_status();
+        let mut fields = Vec::new();
+        for word in &self.words {
+            fields.extend(expand_word(word, shell)?);
+        }
+        // An interrupted command substitution abandons the command.
+        if let Some(Control::Abort(status)) = shell.control() {
+            return Ok(status);
+        }
+        if shell.xtrace() && !fields.is_empty() {
+            trace(&fields, shell);
+        }
+        let Some((name, args)) = fields.split_first() else {
+            assign(&self.assignments, shell, false)?;
+            let status = shell.take_substitution_status().unwrap_or(0);
+            return with_redirects(&self.redirects, shell, |_| Ok(status));
+        };
+        // Assignments in front of a command only last for that command and
+        // are exported to it.
+        let saved: Vec<(&str, Option<Variable>)> = self
+            .assignments
+            .iter()
+            .map(|a| (a.name.as_str(), shell.vars().get_var(&a.name).cloned()))
+            .collect();
+        let result = assign(&self.assignments, shell, true).and_then(|()| {
+            with_redirects(&self.redirects, shell, |shell| {
+                let result = Cmd::new(name).execute(args, shell);
+                Ok(recover(result, shell))
+            })
+        });
+        for (name, var) in saved.into_iter().rev() {
+            shell.vars_mut().restore(name, var);
+        }
+        result
+    }
+}
+
+// Prints a command about to run for `-x`, quoting the fields that need it.
+fn trace(fields: &[String], shell: &Shell) {
+    let prefix = shell.vars().get("PS4").unwrap_or("+ ");
+    let fields: Vec<String> = fields.iter().map(|field| quote(field)).collect();
+    let _ = writeln!(shell.fds().stream(STDERR), "{prefix}{}", fields.join(" "));
+}
+
+/// Single-quotes a field when the shell would not read it back unchanged.
+pub fn quote(field: &str) -> String {
+    let plain = !field.is_empty()
+        && field
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_./=:,+@%^".contains(c));
+    if plain {
+        field.to_string()
+    } else {
+        format!("'{}'", field.replace('\'', "'\\''"))
+    }
+}
+
+fn assign(assignments: &[Assignment], shell: &mut Shell, export: bool) -> Result<(), String> {
+    for assignment in assignments {
+        let value = expand_assignment(&assignment.value, shell)?;
+        shell.vars_mut().set(&assignment.name, value);
+        if export {
+            shell.vars_mut().export(&assignment.name);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn run(input: &str, shell: &mut Shell) -> Result<i32, String> {
+        parse(input, shell.options().extglob)?.run(shell)
+    }
+
+    fn capture(input: &str, shell: &mut Shell) -> String {
+        let (read, write) = sys::pipe().unwrap();
+        let saved = shell.fds().clone();
+        shell.fds_mut().set(STDOUT, Rc::new(write));
+        let result = run(input, shell);
+        *shell.fds_mut() = saved;
+        result.unwrap();
+        let mut output = String::new();
+        File::from(read).read_to_string(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_run_simple_command() {
+        let mut shell = Shell::new();
+        assert!(run("echo 'hello   world'", &mut shell).is_ok());
+        assert_eq!(shell.history().iter().next().unwrap(), "echo hello   world");
+    }
+
+    #[test]
+    fn test_run_sequential_list() {
+        let mut shell = Shell::new();
+        assert!(run("echo a; echo b\necho c", &mut shell).is_ok());
+        assert_eq!(shell.history().len(), 3);
+    }
+
+    #[test]
+    fn test_run_brace_group() {
+        let mut shell = Shell::new();
+        assert!(run("{ echo a; echo b; }", &mut shell).is_ok());
+        assert_eq!(shell.history().len(), 2);
+    }
+
+    #[test]
+    fn test_run_subshell_is_isolated() {
+        let mut shell = Shell::new();
+        let script = "x=outer
+            (cd / && x=inner && f() { :; } && shopt -s nullglob && pwd && exit 3)
+            echo $? $x";
+        assert_eq!(
+            capture(script, &mut shell),
+            "The current directory is: /\n3 outer\n"
+        );
+        assert!(shell.function("f").is_none());
+        assert!(!shell.options().nullglob);
+        assert_eq!(run("(false)", &mut shell), Ok(1));
+    }
+
+    #[test]
+    fn test_run_grouped_redirects() {
+        let mut shell = Shell::new();
+        let path = std::env::temp_dir().join(format!("shell-group-{}", std::process::id()));
+        let script = format!(
+            "{{ echo a; echo b; }} > {0}; (echo c; echo d) >> {0}; x=1; {{ x=2; }}; echo $x",
+            path.display()
+        );
+        assert_eq!(capture(&script, &mut shell), "2\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nb\nc\nd\n");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_run_pipeline_external() {
+        let mut shell = Shell::new();
+        let output = capture("printf 'b\\na\\nb\\n' | sort | uniq -c", &mut shell);
+        let lines: Vec<&str> = output.lines().map(str::trim).collect();
+        assert_eq!(lines, vec!["1 a", "2 b"]);
+    }
+
+    #[test]
+    fn test_run_pipeline_builtin_stage() {
+        let mut shell = Shell::new();
+        let output = capture("echo hello world | tr a-z A-Z", &mut shell);
+        assert_eq!(output, "HELLO WORLD\n");
+    }
+
+    #[test]
+    fn test_run_pipeline_into_brace_group() {
+        let mut shell = Shell::new();
+        let output = capture("echo one | { cat; echo two; }", &mut shell);
+        assert_eq!(output, "one\ntwo\n");
+    }
+
+    #[test]
+    fn test_run_pipeline_history_stage() {
+        let mut shell = Shell::new();
+        run("echo a; echo b", &mut shell).unwrap();
+        let output = capture("history | tail -n 1", &mut shell);
+        assert_eq!(output, "2 echo b\n");
+    }
+
+    #[test]
+    fn test_run_pipeline_is_concurrent() {
+        let mut shell = Shell::new();
+        let output = capture("yes | head -n 2", &mut shell);
+        assert_eq!(output, "y\ny\n");
+    }
+
+    #[test]
+    fn test_run_and_list_short_circuits() {
+        let mut shell = Shell::new();
+        assert_eq!(capture("true && echo yes", &mut shell), "yes\n");
+        assert_eq!(capture("false && echo yes", &mut shell), "");
+        assert_eq!(run("false && echo yes", &mut shell), Ok(1));
+    }
+
+    #[test]
+    fn test_run_or_list_short_circuits() {
+        let mut shell = Shell::new();
+        assert_eq!(capture("false || echo fallback", &mut shell), "fallback\n");
+        assert_eq!(capture("true || echo fallback", &mut shell), "");
+        assert_eq!(run("true || false", &mut shell), Ok(0));
+    }
+
+    #[test]
+    fn test_run_mixed_and_or_is_left_associative() {
+        let mut shell = Shell::new();
+        let output = capture("false && echo a || echo b && echo c", &mut shell);
+        assert_eq!(output, "b\nc\n");
+    }
+
+    #[test]
+    fn test_run_status_codes() {
+        let mut shell = Shell::new();
+        assert_eq!(run("sh -c 'exit 3'", &mut shell), Ok(3));
+        assert_eq!(shell.last_status(), 3);
+        assert_eq!(
+            run("nonexistentcommand123 2>/dev/null", &mut shell),
+            Ok(127)
+        );
+        assert_eq!(
+            run("sh -c 'kill -TERM $$'", &mut shell),
+            Ok(128 + libc::SIGTERM)
+        );
+        assert_eq!(run("! sh -c 'exit 3'", &mut shell), Ok(0));
+        assert_eq!(shell.last_status(), 0);
+    }
+
+    #[test]
+    fn test_run_not_executable() {
+        let path = std::env::temp_dir().join(format!("shell-noexec-{}", std::process::id()));
+        std::fs::write(&path, "echo hi\n").unwrap();
+        let mut shell = Shell::new();
+        let line = format!("{} 2>/dev/null", path.display());
+        assert_eq!(run(&line, &mut shell), Ok(126));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_run_last_status_expansion() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            capture("false; echo $? \"$?\" '$?'", &mut shell),
+            "1 1 $?\n"
+        );
+        assert_eq!(
+            capture("sh -c 'exit 7' || echo status=$?", &mut shell),
+            "status=7\n"
+        );
+    }
+
+    #[test]
+    fn test_run_error_counts_as_failure() {
+        let mut shell = Shell::new();
+        let output = capture(
+            "nonexistentcommand123 2>/dev/null || echo recovered",
+            &mut shell,
+        );
+        assert_eq!(output, "recovered\n");
+    }
+
+    #[test]
+    fn test_run_sequence_continues_after_failure() {
+        let mut shell = Shell::new();
+        assert_eq!(capture("false; echo next", &mut shell), "next\n");
+        assert_eq!(run("true; false", &mut shell), Ok(1));
+    }
+
+    #[test]
+    fn test_run_pipeline_status_is_last_stage() {
+        let mut shell = Shell::new();
+        assert_eq!(run("false | true", &mut shell), Ok(0));
+        assert_eq!(run("true | false", &mut shell), Ok(1));
+        assert_eq!(run("! true | false", &mut shell), Ok(0));
+    }
+
+    #[test]
+    fn test_run_variable_assignment() {
+        let mut shell = Shell::new();
+        run("A=1 B=$A-2", &mut shell).unwrap();
+        assert_eq!(shell.vars().get("A"), Some("1"));
+        assert_eq!(shell.vars().get("B"), Some("1-2"));
+        assert_eq!(capture("echo $A ${B} \"$B\"", &mut shell), "1 1-2 1-2\n");
+        run("C='x  y'", &mut shell).unwrap();
+        assert_eq!(shell.vars().get("C"), Some("x  y"));
+    }
+
+    #[test]
+    fn test_run_unset_variable_drops_field() {
+        let mut shell = Shell::new();
+        shell.vars_mut().unset("UNSET_VAR");
+        assert_eq!(
+            capture("printf '[%s]' $UNSET_VAR a \"$UNSET_VAR\"", &mut shell),
+            "[a][]"
+        );
+        // A command made only of empty expansions runs nothing.
+        assert_eq!(run("$UNSET_VAR", &mut shell), Ok(0));
+    }
+
+    #[test]
+    fn test_run_prefix_assignment_is_temporary() {
+        let mut shell = Shell::new();
+        run("V=outer", &mut shell).unwrap();
+        let output = capture("V=inner sh -c 'echo $V'; echo $V", &mut shell);
+        assert_eq!(output, "inner\nouter\n");
+        shell.vars_mut().unset("W");
+        run("W=temp true", &mut shell).unwrap();
+        assert_eq!(shell.vars().get("W"), None);
+    }
+
+    #[test]
+    fn test_run_exported_variables_reach_children() {
+        let mut shell = Shell::new();
+        run("LOCAL_ONLY=a; EXPORTED=b; export EXPORTED", &mut shell).unwrap();
+        let output = capture("sh -c 'echo \"[$LOCAL_ONLY][$EXPORTED]\"'", &mut shell);
+        assert_eq!(output, "[][b]\n");
+    }
+
+    #[test]
+    fn test_run_uses_shell_path() {
+        let mut shell = Shell::new();
+        run("PATH=/nonexistent", &mut shell).unwrap();
+        assert_eq!(run("ls 2>/dev/null", &mut shell), Ok(127));
+    }
+
+    #[test]
+    fn test_run_command_substitution() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            capture("echo \"built at $(echo now)\" `echo tick`", &mut shell),
+            "built at now tick\n"
+        );
+        assert_eq!(
+            capture(
+                "echo $(echo $(echo nested) `echo \\`echo deep\\``)",
+                &mut shell
+            ),
+            "nested deep\n"
+        );
+        assert_eq!(
+            capture("echo \"[$(printf 'a\\n\\n\\n')]\"", &mut shell),
+            "[a]\n"
+        );
+        assert_eq!(
+            capture("echo $(printf 'a b' | tr a-z A-Z)", &mut shell),
+            "A B\n"
+        );
+    }
+
+    #[test]
+    fn test_run_command_substitution_is_isolated() {
+        let mut shell = Shell::new();
+        run("X=outer", &mut shell).unwrap();
+        let output = capture("echo $(X=inner; echo $X) $X", &mut shell);
+        assert_eq!(output, "inner outer\n");
+    }
+
+    #[test]
+    fn test_run_command_substitution_status() {
+        let mut shell = Shell::new();
+        assert_eq!(run("X=$(exit 3)", &mut shell), Ok(3));
+        assert_eq!(run("X=$(true)", &mut shell), Ok(0));
+        assert_eq!(run("false; X=$?", &mut shell), Ok(0));
+        assert_eq!(shell.vars().get("X"), Some("1"));
+        assert_eq!(run("$(false)", &mut shell), Ok(1));
+        assert_eq!(run("echo $(false) >/dev/null", &mut shell), Ok(0));
+    }
+
+    #[test]
+    fn test_run_command_substitution_large_output() {
+        let mut shell = Shell::new();
+        run("X=$(yes | head -n 100000)", &mut shell).unwrap();
+        assert_eq!(shell.vars().get("X").unwrap().len(), 199_999);
+    }
+
+    #[test]
+    fn test_run_arithmetic() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            capture(
+                "x=4; echo $((x * 2 + 1)) $(( (1 + 2) ** 2 )) \"$((x > 3))\"",
+                &mut shell
+            ),
+            "9 9 1\n"
+        );
+        assert_eq!(
+            capture("((x++)); echo $x $((x -= 2)) $x", &mut shell),
+            "5 3 3\n"
+        );
+        assert_eq!(run("((0))", &mut shell), Ok(1));
+        assert_eq!(run("((x))", &mut shell), Ok(0));
+        assert_eq!(
+            capture("n=3; (( n > 2 )) && echo big || echo small", &mut shell),
+            "big\n"
+        );
+        assert_eq!(
+            capture("y=$(( $(echo 6) / 2 )); echo $y", &mut shell),
+            "3\n"
+        );
+        assert_eq!(capture("v=5; echo ${V:-$((v + 1))}", &mut shell), "6\n");
+    }
+
+    #[test]
+    fn test_run_arithmetic_division_by_zero() {
+        let mut shell = Shell::new();
+        let path = std::env::temp_dir().join(format!("shell-divzero-{}", std::process::id()));
+        shell.fds_mut().set(
+            crate::fds::STDERR,
+            Rc::new(File::create(&path).unwrap().into()),
+        );
+        assert_eq!(run("echo $((1 / 0))", &mut shell), Ok(1));
+        assert_eq!(run("((5 % 0))", &mut shell), Ok(1));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "Error: 1 / 0: division by 0\nError: 5 % 0: division by 0\n"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_run_process_substitution() {
+        let mut shell = Shell::new();
+        let output = capture("cat <(echo one) <(printf 'two\\n')", &mut shell);
+        assert_eq!(output, "one\ntwo\n");
+        let output = capture("cat < <(echo redirected)", &mut shell);
+        assert_eq!(output, "redirected\n");
+        let output = capture("echo <(true)", &mut shell);
+        assert!(output.starts_with("/dev/fd/"));
+        assert_eq!(shell.process_substitution_count(), 0);
+    }
+
+    #[test]
+    fn test_run_arithmetic_comparison_is_not_process_substitution() {
+        let mut shell = Shell::new();
+        let output = capture("n=3; for ((i=0;i<(n);i++)); do echo $i; done", &mut shell);
+        assert_eq!(output, "0\n1\n2\n");
+        assert_eq!(capture("echo $((1<(2))) $((1>(2)))", &mut shell), "1 0\n");
+        assert_eq!(run("(( 1 <(2) ))", &mut shell), Ok(0));
+        assert_eq!(shell.process_substitution_count(), 0);
+    }
+
+    #[test]
+    fn test_run_output_process_substitution() {
+        let mut shell = Shell::new();
+        let output = capture("echo abc > >(tr a-c x-z)", &mut shell);
+        assert_eq!(output, "xyz\n");
+    }
+
+    #[test]
+    fn test_run_if() {
+        let mut shell = Shell::new();
+        let output = capture(
+            "if false; then echo a; elif true; then echo b; else echo c; fi",
+            &mut shell,
+        );
+        assert_eq!(output, "b\n");
+        let output = capture("if false; then echo a; else echo c; fi", &mut shell);
+        assert_eq!(output, "c\n");
+        assert_eq!(run("if false; then true; fi", &mut shell), Ok(0));
+        assert_eq!(run("if true; then false; fi", &mut shell), Ok(1));
+        let output = capture("if ! false && true; then echo yes; fi | tr y Y", &mut shell);
+        assert_eq!(output, "Yes\n");
+    }
+
+    #[test]
+    fn test_run_for_loop() {
+        let mut shell = Shell::new();
+        let output = capture(
+            "for x in a 'b c' {1..2}; do echo \"<$x>\"; done",
+            &mut shell,
+        );
+        assert_eq!(output, "<a>\n<b c>\n<1>\n<2>\n");
+        assert_eq!(shell.vars().get("x"), Some("2"));
+        assert_eq!(run("for x in; do false; done", &mut shell), Ok(0));
+        assert_eq!(run("for x in a; do false; done", &mut shell), Ok(1));
+    }
+
+    #[test]
+    fn test_run_arithmetic_for_loop() {
+        let mut shell = Shell::new();
+        let output = capture("for ((i = 0; i < 3; i++)); do echo $i; done", &mut shell);
+        assert_eq!(output, "0\n1\n2\n");
+        let output = capture("for ((;;)) do echo once; break; done", &mut shell);
+        assert_eq!(output, "once\n");
+    }
+
+    #[test]
+    fn test_run_while_and_until() {
+        let mut shell = Shell::new();
+        let output = capture(
+            "n=0; while ((n < 3)); do echo $n; let n++; done",
+            &mut shell,
+        );
+        assert_eq!(output, "0\n1\n2\n");
+        let output = capture(
+            "n=0; until ((n == 2)); do let n++; done; echo $n",
+            &mut shell,
+        );
+        assert_eq!(output, "2\n");
+        assert_eq!(run("while false; do true; done", &mut shell), Ok(0));
+    }
+
+    #[test]
+    fn test_run_break_and_continue() {
+        let mut shell = Shell::new();
+        let script = "for i in 1 2 3; do
+            for j in a b c; do
+                if [ $j = b ]; then continue 2; fi
+                if [ $i = 3 ]; then break 2; fi
+                echo $i$j
+            done
+            echo never
+        done
+        echo end";
+        assert_eq!(capture(script, &mut shell), "1a\n2a\nend\n");
+        let output = capture("for i in 1 2; do echo $i; break; echo no; done", &mut shell);
+        assert_eq!(output, "1\n");
+        let output = capture("while true; do break && echo no; done; echo $?", &mut shell);
+        assert_eq!(output, "0\n");
+        assert_eq!(shell.control(), None);
+    }
+
+    #[test]
+    fn test_run_case() {
+        let mut shell = Shell::new();
+        let script = "for arg in start run stop 'a*' other; do
+            case \"$arg\" in
+                start|run) echo go ;;
+                stop) echo halt ;;
+                'a*') echo quoted ;;
+                *) echo \"unknown $arg\" ;;
+            esac
+        done";
+        assert_eq!(
+            capture(script, &mut shell),
+            "go\ngo\nhalt\nquoted\nunknown other\n"
+        );
+        assert_eq!(
+            capture(
+                "case abc in a\\*) echo no;; (a*) echo yes; esac",
+                &mut shell
+            ),
+            "yes\n"
+        );
+        assert_eq!(run("case x in y) true;; esac", &mut shell), Ok(0));
+        assert_eq!(run("case x in x) false;; esac", &mut shell), Ok(1));
+    }
+
+    #[test]
+    fn test_run_case_terminators() {
+        let mut shell = Shell::new();
+        let script = "case b in a) echo a;& b) echo b;& c) echo c;; d) echo d;; esac";
+        assert_eq!(capture(script, &mut shell), "b\nc\n");
+        let script = "case ab in a*) echo 1;;& *b) echo 2;;& x) echo 3;; *) echo 4;; esac";
+        assert_eq!(capture(script, &mut shell), "1\n2\n4\n");
+    }
+
+    #[test]
+    fn test_run_case_extglob() {
+        let mut shell = Shell::new();
+        let script = "case ab in @(ab|cd)) echo yes;; esac";
+        assert!(run(script, &mut shell).is_err());
+        run("shopt -s extglob", &mut shell).unwrap();
+        assert_eq!(capture(script, &mut shell), "yes\n");
+    }
+
+    #[test]
+    fn test_run_functions() {
+        let mut shell = Shell::new();
+        let script = "greet() { echo \"hello $1 ($#)\"; }
+            function twice { greet $1; greet \"$2\"; }
+            twice a 'b c'; echo \"[$1]\"";
+        assert_eq!(
+            capture(script, &mut shell),
+            "hello a (1)\nhello b c (1)\n[]\n"
+        );
+        assert_eq!(run("f() { false; }; f", &mut shell), Ok(1));
+        assert_eq!(
+            capture("f() { for arg; do echo $arg; done; }; f x y", &mut shell),
+            "x\ny\n"
+        );
+        assert_eq!(
+            capture(
+                "f() { echo redirected; } >/dev/null; f; echo shown",
+                &mut shell
+            ),
+            "shown\n"
+        );
+    }
+
+    #[test]
+    fn test_run_function_return() {
+        let mut shell = Shell::new();
+        let script = "f() { for i in 1 2 3; do while true; do return $i; done; done; echo no; }
+            f; echo $?; f && echo no";
+        assert_eq!(capture(script, &mut shell), "1\n");
+        assert_eq!(shell.control(), None);
+        assert_eq!(run("g() { false; return; }; g", &mut shell), Ok(1));
+    }
+
+    #[test]
+    fn test_run_function_locals() {
+        let mut shell = Shell::new();
+        let script = "x=global
+            show() { echo $x; }
+            f() { local x=local; show; x=changed; show; }
+            f; show
+            g() { local y; y=inner; }
+            g; echo \"[$y]\"";
+        assert_eq!(capture(script, &mut shell), "local\nchanged\nglobal\n[]\n");
+    }
+
+    #[test]
+    fn test_run_errexit_sets_exit_control() {
+        let mut shell = Shell::new();
+        shell.set_errexit(true);
+        let script = "f() { for i in 1 2; do false; echo no; done; }; f; echo no";
+        assert_eq!(capture(script, &mut shell), "");
+        assert_eq!(shell.take_control(), Some(Control::Exit(1)));
+        assert_eq!(
+            capture("false || true; ! true; echo yes", &mut shell),
+            "yes\n"
+        );
+        assert_eq!(shell.control(), None);
+    }
+}
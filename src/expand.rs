@@ -0,0 +1,1235 @@
+use crate::arith;
+use crate::brace::expand_braces;
+use crate::exec::{capture_output, process_substitution};
+use crate::glob::glob;
+use crate::parser::parse;
+use crate::pattern::Pattern;
+use crate::shell::{Control, Shell};
+use crate::vars::is_valid_name;
+use std::env;
+use std::fs;
+use std::mem;
+
+/// Expands a raw word from the parser into its fields, removing quotes.
+///
+/// Brace expansion comes first and may turn the word into several. An unquoted
+/// word that expands to nothing produces no field at all, as POSIX requires for
+/// unset or empty variables. Unquoted wildcards are matched against the
+/// filesystem; a pattern that matches nothing is left as it is unless
+/// `nullglob` or `failglob` is set.
+pub fn expand_word(word: &str, shell: &mut Shell) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    for word in expand_braces(word) {
+        fields.extend(expand_fields(&word, shell)?);
+    }
+    Ok(fields)
+}
+
+fn expand_fields(word: &str, shell: &mut Shell) -> Result<Vec<String>, String> {
+    let mut expander = Expander::new(word, shell);
+    expander.split = true;
+    expander.command_word = true;
+    // Arguments such as `PATH=~/bin` get the same tildes as assignments.
+    expander.assignment = word
+        .split_once('=')
+        .is_some_and(|(name, _)| is_valid_name(name));
+    expander.word()?;
+    if expander.field_started() {
+        expander.end_field();
+    }
+    let options = expander.shell.options();
+    let mut fields = Vec::new();
+    for field in expander.fields {
+        if field.has_glob {
+            let matches = glob(&field.pattern, options);
+            if !matches.is_empty() {
+                fields.extend(matches);
+                continue;
+            }
+            if options.failglob {
+                return Err(format!("no match: {}", field.text));
+            }
+            if options.nullglob {
+                continue;
+            }
+        }
+        fields.push(field.text);
+    }
+    Ok(fields)
+}
+
+/// Expands a word that always yields exactly one string, such as an assignment value.
+pub fn expand_string(word: &str, shell: &mut Shell) -> Result<String, String> {
+    let mut expander = Expander::new(word, shell);
+    expander.word()?;
+    Ok(expander.text)
+}
+
+/// Expands a word into a pattern, such as a `case` pattern, in which quoted
+/// characters match literally.
+pub fn expand_pattern(word: &str, shell: &mut Shell) -> Result<Pattern, String> {
+    let mut expander = Expander::new(word, shell);
+    expander.word()?;
+    Ok(expander.compile(&expander.pattern))
+}
+
+/// Expands the value of a `NAME=value` assignment, which also expands a tilde
+/// after each `:` as in `PATH=~/bin:~/.local/bin`.
+pub fn expand_assignment(value: &str, shell: &mut Shell) -> Result<String, String> {
+    let mut expander = Expander::new(value, shell);
+    expander.assignment = true;
+    expander.word()?;
+    Ok(expander.text)
+}
+
+/// Expands the body of an unquoted here-document, where quotes are ordinary characters.
+pub fn expand_heredoc(body: &str, shell: &mut Shell) -> Result<String, String> {
+    let mut expander = Expander::new(body, shell);
+    expander.in_quotes = true;
+    while let Some(c) = expander.bump() {
+        match c {
+            '\\' => match expander.bump() {
+                Some('\n') => {}
+                Some(c @ ('$' | '`' | '\\')) => expander.text.push(c),
+                Some(c) => {
+                    expander.text.push('\\');
+                    expander.text.push(c);
+                }
+                None => expander.text.push('\\'),
+            },
+            '$' => expander.dollar()?,
+            '`' => expander.backquoted()?,
+            _ => expander.text.push(c),
+        }
+    }
+    Ok(expander.text)
+}
+
+const DEFAULT_IFS: &str = " \t\n";
+
+// A field produced by splitting, kept with what pathname expansion needs.
+struct Field {
+    text: String,
+    pattern: String,
+    has_glob: bool,
+}
+
+struct Expander<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    shell: &'a mut Shell,
+    // The field being built: its text, the same text with quoted characters
+    // escaped for use as a pattern, and whether any part of it was quoted.
+    text: String,
+    pattern: String,
+    quoted: bool,
+    has_glob: bool,
+    in_quotes: bool,
+    // Set for assignment values, where `~` also follows `=` and `:`.
+    assignment: bool,
+    // Field splitting applies to the unquoted results of expansions, and only
+    // when the word may become several fields.
+    split: bool,
+    // Set for command words, the only place `<(...)` and `>(...)` start a
+    // process; in arithmetic they are comparisons.
+    command_word: bool,
+    in_expansion: bool,
+    ifs: String,
+    // IFS whitespace was seen; it ends the field if anything follows.
+    split_pending: bool,
+    // A quoted `$@` expanded to no parameters, so its quotes make no field.
+    empty_at: bool,
+    fields: Vec<Field>,
+}
+
+impl<'a> Expander<'a> {
+    fn new(word: &str, shell: &'a mut Shell) -> Self {
+        let ifs = shell.vars().get("IFS").unwrap_or(DEFAULT_IFS).to_string();
+        Expander {
+            chars: word.chars().collect(),
+            pos: 0,
+            shell,
+            text: String::new(),
+            pattern: String::new(),
+            quoted: false,
+            has_glob: false,
+            in_quotes: false,
+            assignment: false,
+            split: false,
+            command_word: false,
+            in_expansion: false,
+            ifs,
+            split_pending: false,
+            empty_at: false,
+            fields: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn push(&mut self, c: char, quoted: bool) {
+        if self.split && self.in_expansion && !quoted && self.ifs.contains(c) {
+            // Whitespace separators collapse into one; every other separator
+            // ends a field, even an empty one.
+            if matches!(c, ' ' | '\t' | '\n') {
+                self.split_pending = true;
+            } else {
+                self.split_pending = false;
+                self.end_field();
+            }
+            return;
+        }
+        if mem::take(&mut self.split_pending) && self.field_started() {
+            self.end_field();
+        }
+        self.text.push(c);
+        if quoted && !c.is_alphanumeric() {
+            self.pattern.push('\\');
+        } else if matches!(c, '*' | '?' | '[' | '(') {
+            self.has_glob = true;
+        }
+        self.pattern.push(c);
+    }
+
+    fn push_value(&mut self, value: &str) {
+        let in_expansion = mem::replace(&mut self.in_expansion, true);
+        for c in value.chars() {
+            self.push(c, self.in_quotes);
+        }
+        self.in_expansion = in_expansion;
+    }
+
+    // Pushes text that is neither split nor globbed, such as a home directory.
+    fn push_quoted(&mut self, value: &str) {
+        for c in value.chars() {
+            self.push(c, true);
+        }
+    }
+
+    fn field_started(&self) -> bool {
+        !self.text.is_empty() || self.quoted
+    }
+
+    fn end_field(&mut self) {
+        self.fields.push(Field {
+            text: mem::take(&mut self.text),
+            pattern: mem::take(&mut self.pattern),
+            has_glob: mem::take(&mut self.has_glob),
+        });
+        self.quoted = false;
+    }
+
+    fn word(&mut self) -> Result<(), String> {
+        while let Some(c) = self.bump() {
+            match c {
+                '\\' => {
+                    self.quoted = true;
+                    if let Some(c) = self.bump() {
+                        self.push(c, true);
+                    }
+                }
+                '\'' if !self.in_quotes => {
+                    self.quoted = true;
+                    while let Some(c) = self.bump() {
+                        if c == '\'' {
+                            break;
+                        }
+                        self.push(c, true);
+                    }
+                }
+                '"' => {
+                    let quoted = mem::replace(&mut self.quoted, true);
+                    self.double_quoted()?;
+                    if mem::take(&mut self.empty_at) {
+                        self.quoted = quoted;
+                    }
+                }
+                '$' => self.dollar()?,
+                '`' => self.backquoted()?,
+                '~' if !self.in_quotes && self.at_tilde_start() => self.tilde(),
+                '<' | '>' if self.command_word && !self.in_quotes && self.peek() == Some('(') => {
+                    self.process_substitution(c == '<')?;
+                }
+                _ => self.push(c, self.in_quotes),
+            }
+        }
+        Ok(())
+    }
+
+    // A tilde is expanded at the start of a word and, in assignments, after
+    // the `=` and after each `:`.
+    fn at_tilde_start(&self) -> bool {
+        let i = self.pos - 1;
+        i == 0 || (self.assignment && matches!(self.chars[i - 1], ':' | '='))
+    }
+
+    // Expands the tilde prefix after a `~` that was already consumed, leaving
+    // the tilde literal when the prefix is quoted or names nothing.
+    fn tilde(&mut self) {
+        let start = self.pos;
+        let end = self.chars[start..]
+            .iter()
+            .position(|&c| c == '/' || (self.assignment && c == ':'))
+            .map_or(self.chars.len(), |len| start + len);
+        let prefix: String = self.chars[start..end].iter().collect();
+        let vars = self.shell.vars();
+        let dir = match prefix.as_str() {
+            _ if prefix.contains(['\\', '\'', '"', '$', '`']) => None,
+            "" => vars
+                .get("HOME")
+                .map(str::to_string)
+                .or_else(|| env::home_dir().map(|dir| dir.display().to_string())),
+            "+" => vars.get("PWD").map(str::to_string),
+            "-" => vars.get("OLDPWD").map(str::to_string),
+            user => user_home(user),
+        };
+        match dir {
+            Some(dir) => {
+                self.pos = end;
+                self.push_quoted(&dir);
+            }
+            None => self.push('~', false),
+        }
+    }
+
+    fn double_quoted(&mut self) -> Result<(), String> {
+        let in_quotes = mem::replace(&mut self.in_quotes, true);
+        while let Some(c) = self.bump() {
+            match c {
+                '"' => break,
+                '\\' => match self.bump() {
+                    Some(c @ ('$' | '`' | '"' | '\\')) => self.push(c, true),
+                    Some(c) => {
+                        self.push('\\', true);
+                        self.push(c, true);
+                    }
+                    None => self.push('\\', true),
+                },
+                '$' => self.dollar()?,
+                '`' => self.backquoted()?,
+                _ => self.push(c, true),
+            }
+        }
+        self.in_quotes = in_quotes;
+        Ok(())
+    }
+
+    /// Expands `word` in place, as part of the word currently being expanded.
+    fn expand_nested(&mut self, word: &str) -> Result<(), String> {
+        let chars = mem::replace(&mut self.chars, word.chars().collect());
+        let pos = mem::replace(&mut self.pos, 0);
+        let in_expansion = mem::replace(&mut self.in_expansion, true);
+        let result = self.word();
+        self.chars = chars;
+        self.pos = pos;
+        self.in_expansion = in_expansion;
+        result
+    }
+
+    /// Expands `word` on its own, returning its text and its pattern form.
+    fn expand_separately(&mut self, word: &str) -> Result<(String, String), String> {
+        let mut expander = Expander::new(word, self.shell);
+        expander.word()?;
+        Ok((expander.text, expander.pattern))
+    }
+
+    fn dollar(&mut self) -> Result<(), String> {
+        let name = match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                let start = self.pos;
+                let inner = self.enclosed('{', '}').ok_or_else(|| {
+                    let inner: String = self.chars[start..].iter().collect();
+                    format!("${{{inner}: bad substitution")
+                })?;
+                return self.parameter_expansion(&inner);
+            }
+            Some('(') => {
+                self.pos += 1;
+                let source = self
+                    .enclosed('(', ')')
+                    .ok_or("unexpected EOF while looking for matching `)'")?;
+                if let Some(expr) = source.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+                    return self.arithmetic(expr);
+                }
+                return self.command_substitution(&source);
+            }
+            Some(c) if is_special_parameter(c) || c.is_ascii_digit() => {
+                self.pos += 1;
+                c.to_string()
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_') {
+                    self.pos += 1;
+                }
+                self.chars[start..self.pos].iter().collect()
+            }
+            _ => {
+                self.push('$', self.in_quotes);
+                return Ok(());
+            }
+        };
+        if name == "@" || name == "*" {
+            self.positional_parameters(name == "@");
+            return Ok(());
+        }
+        let value = parameter(self.shell, &name).unwrap_or_default();
+        self.push_value(&value);
+        Ok(())
+    }
+
+    // Expands `$@` or `$*` to one field per positional parameter, each split
+    // further when unquoted. `"$@"` keeps the parameters as they are, while
+    // `"$*"` joins them with the first character of IFS.
+    fn positional_parameters(&mut self, at: bool) {
+        let params = self.shell.positional().to_vec();
+        if !self.split || (self.in_quotes && !at) {
+            let separator = match self.ifs.chars().next() {
+                Some(c) if !at => c.to_string(),
+                None if !at => String::new(),
+                _ => " ".to_string(),
+            };
+            self.push_value(&params.join(&separator));
+            return;
+        }
+        if self.in_quotes && params.is_empty() {
+            self.empty_at = true;
+        }
+        for (i, param) in params.iter().enumerate() {
+            if i > 0 {
+                if self.in_quotes {
+                    self.end_field();
+                    self.quoted = true;
+                } else {
+                    self.split_pending = true;
+                }
+            }
+            self.push_value(param);
+        }
+    }
+
+    // Returns the raw text up to the `close` that balances an already consumed
+    // `open`, or `None` if the word ends first.
+    fn enclosed(&mut self, open: char, close: char) -> Option<String> {
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.bump() {
+            match c {
+                '\\' => {
+                    self.bump();
+                }
+                '\'' => while !matches!(self.bump(), Some('\'') | None) {},
+                '"' | '`' => {
+                    while let Some(next) = self.bump() {
+                        match next {
+                            _ if next == c => break,
+                            '\\' => {
+                                self.bump();
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ if c == open => depth += 1,
+                _ if c == close && depth == 0 => {
+                    return Some(self.chars[start..self.pos - 1].iter().collect());
+                }
+                _ if c == close => depth -= 1,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn arithmetic(&mut self, expr: &str) -> Result<(), String> {
+        let (expr, _) = self.expand_separately(expr)?;
+        let value = arith::evaluate(&expr, self.shell)?;
+        self.push_value(&value.to_string());
+        Ok(())
+    }
+
+    fn command_substitution(&mut self, source: &str) -> Result<(), String> {
+        let list = parse(source, self.shell.options().extglob)?;
+        let (output, status) = capture_output(&list, self.shell)?;
+        self.shell.set_substitution_status(status);
+        // Values are strings, so bytes that are not UTF-8 become U+FFFD.
+        let output = String::from_utf8_lossy(&output);
+        self.push_value(output.trim_end_matches('\n'));
+        Ok(())
+    }
+
+    fn process_substitution(&mut self, input: bool) -> Result<(), String> {
+        self.pos += 1;
+        let source = self
+            .enclosed('(', ')')
+            .ok_or("unexpected EOF while looking for matching `)'")?;
+        let list = parse(&source, self.shell.options().extglob)?;
+        let path = process_substitution(&list, input, self.shell)?;
+        self.push_quoted(&path);
+        Ok(())
+    }
+
+    // Inside backquotes a backslash only escapes `$`, `` ` `` and `\`, plus `"`
+    // when the backquotes are themselves inside double quotes.
+    fn backquoted(&mut self) -> Result<(), String> {
+        let mut source = String::new();
+        loop {
+            match self.bump() {
+                Some('`') => break,
+                Some('\\') => match self.bump() {
+                    Some(c @ ('$' | '`' | '\\')) => source.push(c),
+                    Some('"') if self.in_quotes => source.push('"'),
+                    Some(c) => {
+                        source.push('\\');
+                        source.push(c);
+                    }
+                    None => source.push('\\'),
+                },
+                Some(c) => source.push(c),
+                None => return Err("unexpected EOF while looking for matching ``'".to_string()),
+            }
+        }
+        self.command_substitution(&source)
+    }
+
+    fn parameter_expansion(&mut self, inner: &str) -> Result<(), String> {
+        if let Some(name) = inner.strip_prefix('#')
+            && !name.is_empty()
+            && parameter_name_len(name) == name.len()
+        {
+            let value = parameter(self.shell, name).unwrap_or_default();
+            self.push_value(&value.chars().count().to_string());
+            return Ok(());
+        }
+        let (name, op) = inner.split_at(parameter_name_len(inner));
+        if name.is_empty() {
+            return Err(format!("${{{inner}}}: bad substitution"));
+        }
+        let value = parameter(self.shell, name);
+        let mut chars = op.chars();
+        match (chars.next(), chars.next()) {
+            (None, _) if name == "@" || name == "*" => self.positional_parameters(name == "@"),
+            (None, _) => self.push_value(&value.unwrap_or_default()),
+            (Some(':'), Some('-' | '=' | '?' | '+')) => {
+                self.conditional(name, value, &op[1..], true)?
+            }
+            (Some('-' | '=' | '?' | '+'), _) => self.conditional(name, value, op, false)?,
+            (Some(':'), _) => self.substring(&value.unwrap_or_default(), &op[1..])?,
+            (Some('#' | '%'), _) => self.remove_affix(&value.unwrap_or_default(), op)?,
+            (Some('/'), _) => self.replace(&value.unwrap_or_default(), &op[1..])?,
+            (Some('^' | ','), _) => self.convert_case(&value.unwrap_or_default(), op)?,
+            _ => return Err(format!("${{{inner}}}: bad substitution")),
+        }
+        Ok(())
+    }
+
+    // `${name-word}`, `${name=word}`, `${name?word}` and `${name+word}`; with a
+    // colon, an empty value counts as unset.
+    fn conditional(
+        &mut self,
+        name: &str,
+        value: Option<String>,
+        op: &str,
+        colon: bool,
+    ) -> Result<(), String> {
+        let (operator, word) = op.split_at(1);
+        let value = value.filter(|value| !(colon && value.is_empty()));
+        match (operator, value) {
+            ("+", Some(_)) => self.expand_nested(word),
+            ("+", None) => Ok(()),
+            (_, Some(value)) => {
+                self.push_value(&value);
+                Ok(())
+            }
+            ("-", None) => self.expand_nested(word),
+            ("=", None) => {
+                if !is_valid_name(name) {
+                    return Err(format!("${name}: cannot assign in this way"));
+                }
+                let (value, _) = self.expand_separately(word)?;
+                self.shell.vars_mut().set(name, value.clone());
+                self.push_value(&value);
+                Ok(())
+            }
+            (_, None) => {
+                let (message, _) = self.expand_separately(word)?;
+                // Unlike other errors, this one ends a non-interactive shell.
+                self.shell.set_control(Some(Control::Abort(1)));
+                if message.is_empty() {
+                    Err(format!("{name}: parameter null or not set"))
+                } else {
+                    Err(format!("{name}: {message}"))
+                }
+            }
+        }
+    }
+
+    fn compile(&self, pattern: &str) -> Pattern {
+        Pattern::with_options(pattern, self.shell.options().pattern_options())
+    }
+
+    fn expand_pattern(&mut self, word: &str) -> Result<Pattern, String> {
+        let (_, pattern) = self.expand_separately(word)?;
+        Ok(self.compile(&pattern))
+    }
+
+    // `${name#pattern}` and `${name%pattern}`, doubled for the longest match.
+    fn remove_affix(&mut self, value: &str, op: &str) -> Result<(), String> {
+        let longest = op[1..].starts_with(&op[..1]);
+        let word = if longest { &op[2..] } else { &op[1..] };
+        let pattern = self.expand_pattern(word)?;
+        let mut bounds = boundaries(value);
+        let result = if op.starts_with('#') {
+            if longest {
+                bounds.reverse();
+            }
+            bounds
+                .into_iter()
+                .find(|&i| pattern.matches(&value[..i]))
+                .map(|i| &value[i..])
+        } else {
+            if !longest {
+                bounds.reverse();
+            }
+            bounds
+                .into_iter()
+                .find(|&i| pattern.matches(&value[i..]))
+                .map(|i| &value[..i])
+        };
+        self.push_value(result.unwrap_or(value));
+        Ok(())
+    }
+
+    // `${name/pattern/string}`, where `//` replaces every match and `/#` and
+    // `/%` anchor the match to the start or end of the value.
+    fn replace(&mut self, value: &str, op: &str) -> Result<(), String> {
+        let (mode, op) = match op.chars().next() {
+            Some(c @ ('/' | '#' | '%')) => (Some(c), &op[1..]),
+            _ => (None, op),
+        };
+        let (pattern, replacement) = split_unquoted(op, '/');
+        let (_, pattern) = self.expand_separately(pattern)?;
+        let replacement = match replacement {
+            Some(word) => self.expand_separately(word)?.0,
+            None => String::new(),
+        };
+        let anchored = matches!(mode, Some('#' | '%'));
+        if pattern.is_empty() && !anchored {
+            self.push_value(value);
+            return Ok(());
+        }
+        let pattern = self.compile(&pattern);
+        let bounds = boundaries(value);
+        let mut result = String::new();
+        let mut copied = 0;
+        let mut k = 0;
+        while k < bounds.len() {
+            let start = bounds[k];
+            if mode == Some('#') && start != 0 {
+                break;
+            }
+            // The longest match wins at the earliest position that has one.
+            let end = bounds[k..].iter().rev().copied().find(|&end| {
+                (end > start || anchored)
+                    && (mode != Some('%') || end == value.len())
+                    && pattern.matches(&value[start..end])
+            });
+            match end {
+                Some(end) => {
+                    result.push_str(&value[copied..start]);
+                    result.push_str(&replacement);
+                    copied = end;
+                    if mode != Some('/') {
+                        break;
+                    }
+                    k += bounds[k..]
+                        .iter()
+                        .position(|&b| b == end)
+                        .unwrap_or(0)
+                        .max(1);
+                }
+                None => k += 1,
+            }
+        }
+        result.push_str(&value[copied..]);
+        self.push_value(&result);
+        Ok(())
+    }
+
+    // `${name^pattern}` and `${name,pattern}` convert the first character to
+    // upper or lower case, doubled to convert every character.
+    fn convert_case(&mut self, value: &str, op: &str) -> Result<(), String> {
+        let all = op[1..].starts_with(&op[..1]);
+        let word = if all { &op[2..] } else { &op[1..] };
+        let pattern = if word.is_empty() {
+            None
+        } else {
+            Some(self.expand_pattern(word)?)
+        };
+        let upper = op.starts_with('^');
+        let mut result = String::new();
+        for (i, c) in value.chars().enumerate() {
+            let convert = (all || i == 0)
+                && pattern
+                    .as_ref()
+                    .is_none_or(|pattern| pattern.matches(&c.to_string()));
+            match (convert, upper) {
+                (true, true) => result.extend(c.to_uppercase()),
+                (true, false) => result.extend(c.to_lowercase()),
+                (false, _) => result.push(c),
+            }
+        }
+        self.push_value(&result);
+        Ok(())
+    }
+
+    // `${name:offset}` and `${name:offset:length}`, counted in characters; a
+    // negative offset counts from the end, as does a negative length.
+    fn substring(&mut self, value: &str, op: &str) -> Result<(), String> {
+        let (offset, length) = split_unquoted(op, ':');
+        let offset = self.number(offset)?;
+        let chars: Vec<char> = value.chars().collect();
+        let len = chars.len() as i64;
+        let start = if offset < 0 { len + offset } else { offset };
+        if start < 0 || start > len {
+            return Ok(());
+        }
+        let end = match length {
+            None => len,
+            Some(length) => match self.number(length)? {
+                length if length < 0 => {
+                    if len + length < start {
+                        return Err(format!("{length}: substring expression < 0"));
+                    }
+                    len + length
+                }
+                length => (start + length).min(len),
+            },
+        };
+        let result: String = chars[start as usize..end as usize].iter().collect();
+        self.push_value(&result);
+        Ok(())
+    }
+
+    fn number(&mut self, word: &str) -> Result<i64, String> {
+        let (expr, _) = self.expand_separately(word)?;
+        arith::evaluate(&expr, self.shell)
+    }
+}
+
+fn is_special_parameter(c: char) -> bool {
+    matches!(c, '?' | '$' | '#' | '@' | '*')
+}
+
+// Returns the length of the parameter name at the start of `text`.
+fn parameter_name_len(text: &str) -> usize {
+    match text.chars().next() {
+        Some(c) if is_special_parameter(c) => 1,
+        Some(c) if c.is_ascii_digit() => text.chars().take_while(char::is_ascii_digit).count(),
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => text
+            .chars()
+            .take_while(|&c| c.is_ascii_alphanumeric() || c == '_')
+            .count(),
+        _ => 0,
+    }
+}
+
+/// Looks up a variable or special parameter, returning `None` when it is unset.
+fn parameter(shell: &Shell, name: &str) -> Option<String> {
+    match name {
+        "?" => Some(shell.last_status().to_string()),
+        "$" => Some(shell.pid().to_string()),
+        "#" => Some(shell.positional().len().to_string()),
+        "0" => Some(shell.name().to_string()),
+        "@" | "*" if !shell.positional().is_empty() => Some(shell.positional().join(" ")),
+        _ => match name.parse::<usize>() {
+            Ok(n) if n >= 1 => shell.positional().get(n - 1).cloned(),
+            _ => shell.vars().get(name).map(str::to_string),
+        },
+    }
+}
+
+// Finds a user's home directory in /etc/passwd.
+fn user_home(user: &str) -> Option<String> {
+    let passwd = fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        (fields.len() >= 6 && fields[0] == user).then(|| fields[5].to_string())
+    })
+}
+
+// Byte offsets of every character boundary in `text`, including both ends.
+fn boundaries(text: &str) -> Vec<usize> {
+    text.char_indices()
+        .map(|(i, _)| i)
+        .chain([text.len()])
+        .collect()
+}
+
+// Splits at the first `sep` that is not quoted, escaped or inside a nested `${...}`.
+fn split_unquoted(text: &str, sep: char) -> (&str, Option<&str>) {
+    let mut chars = text.char_indices();
+    let mut quote = None;
+    let mut depth = 0;
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') => depth -= 1,
+            (None, c) if c == sep && depth == 0 => {
+                return (&text[..i], Some(&text[i + c.len_utf8()..]));
+            }
+            _ => {}
+        }
+    }
+    (text, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(word: &str, shell: &mut Shell) -> Vec<String> {
+        expand_word(word, shell).unwrap()
+    }
+
+    #[test]
+    fn test_expand_removes_quotes() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            expand(r#"'a b'"c\"d"e\ f"#, &mut shell),
+            vec![r#"a bc"de f"#]
+        );
+    }
+
+    #[test]
+    fn test_expand_last_status() {
+        let mut shell = Shell::new();
+        shell.set_last_status(42);
+        assert_eq!(expand("$?", &mut shell), vec!["42"]);
+        assert_eq!(expand("\"code=$?\"", &mut shell), vec!["code=42"]);
+        assert_eq!(expand("'$?'", &mut shell), vec!["$?"]);
+        assert_eq!(expand("\\$?", &mut shell), vec!["$?"]);
+    }
+
+    #[test]
+    fn test_expand_lone_dollar() {
+        let mut shell = Shell::new();
+        assert_eq!(expand("$", &mut shell), vec!["$"]);
+        assert_eq!(expand("a$ b", &mut shell), vec!["a$ b"]);
+        assert_eq!(expand("\"$\"", &mut shell), vec!["$"]);
+    }
+
+    #[test]
+    fn test_expand_variables() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("NAME", "world".to_string());
+        assert_eq!(expand("$NAME", &mut shell), vec!["world"]);
+        assert_eq!(expand("${NAME}", &mut shell), vec!["world"]);
+        assert_eq!(expand("hello,$NAME!", &mut shell), vec!["hello,world!"]);
+        assert_eq!(expand("${NAME}s", &mut shell), vec!["worlds"]);
+        assert_eq!(expand("$NAMEs", &mut shell), Vec::<String>::new());
+        assert_eq!(expand("\"[$NAME]\"", &mut shell), vec!["[world]"]);
+        assert_eq!(expand("'$NAME'", &mut shell), vec!["$NAME"]);
+    }
+
+    #[test]
+    fn test_expand_unset_variable() {
+        let mut shell = Shell::new();
+        shell.vars_mut().unset("UNSET_VAR");
+        assert_eq!(expand("$UNSET_VAR", &mut shell), Vec::<String>::new());
+        assert_eq!(expand("${UNSET_VAR}", &mut shell), Vec::<String>::new());
+        assert_eq!(expand("\"$UNSET_VAR\"", &mut shell), vec![""]);
+        assert_eq!(expand("''$UNSET_VAR", &mut shell), vec![""]);
+        assert_eq!(expand("a${UNSET_VAR}b", &mut shell), vec!["ab"]);
+    }
+
+    #[test]
+    fn test_expand_bad_substitution() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            expand_word("${a b}", &mut shell),
+            Err("${a b}: bad substitution".to_string())
+        );
+        assert!(expand_word("${}", &mut shell).is_err());
+    }
+
+    #[test]
+    fn test_expand_string_keeps_empty() {
+        let mut shell = Shell::new();
+        shell.vars_mut().unset("UNSET_VAR");
+        assert_eq!(expand_string("$UNSET_VAR", &mut shell), Ok(String::new()));
+    }
+
+    #[test]
+    fn test_expand_heredoc() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("X", "1".to_string());
+        assert_eq!(
+            expand_heredoc("x=$X '$X' \"${X}\" \\$X \\n\n", &mut shell),
+            Ok("x=1 '1' \"1\" $X \\n\n".to_string())
+        );
+    }
+
+    fn expand_one(word: &str, shell: &mut Shell) -> String {
+        expand_string(word, shell).unwrap()
+    }
+
+    #[test]
+    fn test_expand_default_and_alternative() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("SET", "value".to_string());
+        shell.vars_mut().set("EMPTY", String::new());
+        shell.vars_mut().unset("UNSET");
+        assert_eq!(expand_one("${UNSET:-default}", &mut shell), "default");
+        assert_eq!(expand_one("${EMPTY:-default}", &mut shell), "default");
+        assert_eq!(expand_one("${EMPTY-default}", &mut shell), "");
+        assert_eq!(expand_one("${SET:-default}", &mut shell), "value");
+        assert_eq!(expand_one("${UNSET:-$SET x}", &mut shell), "value x");
+        assert_eq!(expand_one("${SET:+alt}", &mut shell), "alt");
+        assert_eq!(expand_one("${EMPTY:+alt}", &mut shell), "");
+        assert_eq!(expand_one("${EMPTY+alt}", &mut shell), "alt");
+        assert_eq!(expand_one("${UNSET+alt}", &mut shell), "");
+        assert_eq!(expand_one("\"${UNSET:-'q'}\"", &mut shell), "'q'");
+        assert_eq!(expand("${UNSET:-\"\"}", &mut shell), vec![""]);
+    }
+
+    #[test]
+    fn test_expand_assign_default() {
+        let mut shell = Shell::new();
+        shell.vars_mut().unset("NEW");
+        assert_eq!(expand_one("${NEW:=assigned}", &mut shell), "assigned");
+        assert_eq!(shell.vars().get("NEW"), Some("assigned"));
+        assert_eq!(expand_one("${NEW:=other}", &mut shell), "assigned");
+        assert_eq!(
+            expand_string("${1:=x}", &mut shell),
+            Err("$1: cannot assign in this way".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expand_error_if_unset() {
+        let mut shell = Shell::new();
+        shell.vars_mut().unset("UNSET");
+        shell.vars_mut().set("EMPTY", String::new());
+        assert_eq!(
+            expand_string("${UNSET:?must be set}", &mut shell),
+            Err("UNSET: must be set".to_string())
+        );
+        assert_eq!(shell.take_control(), Some(Control::Abort(1)));
+        assert_eq!(
+            expand_string("${EMPTY:?}", &mut shell),
+            Err("EMPTY: parameter null or not set".to_string())
+        );
+        assert_eq!(expand_string("${EMPTY?}", &mut shell), Ok(String::new()));
+        assert_eq!(shell.control(), Some(Control::Abort(1)));
+    }
+
+    #[test]
+    fn test_expand_length() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("V", "héllo".to_string());
+        shell.vars_mut().unset("UNSET");
+        assert_eq!(expand_one("${#V}", &mut shell), "5");
+        assert_eq!(expand_one("${#UNSET}", &mut shell), "0");
+    }
+
+    #[test]
+    fn test_expand_remove_prefix_and_suffix() {
+        let mut shell = Shell::new();
+        shell
+            .vars_mut()
+            .set("P", "/usr/local/lib.tar.gz".to_string());
+        assert_eq!(expand_one("${P#*/}", &mut shell), "usr/local/lib.tar.gz");
+        assert_eq!(expand_one("${P##*/}", &mut shell), "lib.tar.gz");
+        assert_eq!(expand_one("${P%.*}", &mut shell), "/usr/local/lib.tar");
+        assert_eq!(expand_one("${P%%.*}", &mut shell), "/usr/local/lib");
+        assert_eq!(
+            expand_one("${P#nomatch}", &mut shell),
+            "/usr/local/lib.tar.gz"
+        );
+        assert_eq!(expand_one("${P%.[a-z]z}", &mut shell), "/usr/local/lib.tar");
+    }
+
+    #[test]
+    fn test_expand_quoted_pattern_is_literal() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("V", "*ab*".to_string());
+        shell.vars_mut().set("STAR", "*".to_string());
+        assert_eq!(expand_one("${V#'*'}", &mut shell), "ab*");
+        assert_eq!(expand_one("${V#\\*}", &mut shell), "ab*");
+        assert_eq!(expand_one("${V#$STAR}", &mut shell), "*ab*");
+        assert_eq!(expand_one("${V##$STAR}", &mut shell), "");
+        assert_eq!(expand_one("${V#\"$STAR\"}", &mut shell), "ab*");
+    }
+
+    #[test]
+    fn test_expand_replace() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("V", "a-b-c".to_string());
+        assert_eq!(expand_one("${V/-/+}", &mut shell), "a+b-c");
+        assert_eq!(expand_one("${V//-/+}", &mut shell), "a+b+c");
+        assert_eq!(expand_one("${V//-}", &mut shell), "abc");
+        assert_eq!(expand_one("${V/#a/X}", &mut shell), "X-b-c");
+        assert_eq!(expand_one("${V/#b/X}", &mut shell), "a-b-c");
+        assert_eq!(expand_one("${V/%c/X}", &mut shell), "a-b-X");
+        assert_eq!(expand_one("${V/-*/}", &mut shell), "a");
+        assert_eq!(expand_one("${V/#/>}", &mut shell), ">a-b-c");
+        assert_eq!(expand_one("${V//[ac]/\"x y\"}", &mut shell), "x y-b-x y");
+        shell.vars_mut().set("PATHS", "a/b".to_string());
+        assert_eq!(expand_one("${PATHS//\\//:}", &mut shell), "a:b");
+    }
+
+    #[test]
+    fn test_expand_case_conversion() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("V", "hello World".to_string());
+        assert_eq!(expand_one("${V^^}", &mut shell), "HELLO WORLD");
+        assert_eq!(expand_one("${V^}", &mut shell), "Hello World");
+        assert_eq!(expand_one("${V,,}", &mut shell), "hello world");
+        assert_eq!(expand_one("${V^^[lo]}", &mut shell), "heLLO WOrLd");
+        shell.vars_mut().set("U", "ABC".to_string());
+        assert_eq!(expand_one("${U,}", &mut shell), "aBC");
+    }
+
+    #[test]
+    fn test_expand_substring() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("V", "abcdef".to_string());
+        assert_eq!(expand_one("${V:2}", &mut shell), "cdef");
+        assert_eq!(expand_one("${V:1:3}", &mut shell), "bcd");
+        assert_eq!(expand_one("${V: -2}", &mut shell), "ef");
+        assert_eq!(expand_one("${V:1:-1}", &mut shell), "bcde");
+        assert_eq!(expand_one("${V:10}", &mut shell), "");
+        assert_eq!(expand_one("${V:2:100}", &mut shell), "cdef");
+        assert_eq!(
+            expand_string("${V:4:-3}", &mut shell),
+            Err("-3: substring expression < 0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expand_command_substitution() {
+        let mut shell = Shell::new();
+        assert_eq!(expand("$(echo hi)", &mut shell), vec!["hi"]);
+        assert_eq!(expand("x`echo hi`y", &mut shell), vec!["xhiy"]);
+        assert_eq!(expand("\"$(echo '$HOME')\"", &mut shell), vec!["$HOME"]);
+        assert_eq!(expand("$(true)", &mut shell), Vec::<String>::new());
+        assert_eq!(expand("\"$(true)\"", &mut shell), vec![""]);
+        assert_eq!(
+            expand("${UNSET_VAR:-$(echo fallback)}", &mut shell),
+            vec!["fallback"]
+        );
+        assert_eq!(
+            expand_heredoc("at `echo noon` $(echo now)\n", &mut shell),
+            Ok("at noon now\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expand_command_substitution_status() {
+        let mut shell = Shell::new();
+        expand("$(exit 4)", &mut shell);
+        assert_eq!(shell.last_status(), 4);
+        assert_eq!(shell.take_substitution_status(), Some(4));
+        assert_eq!(shell.take_substitution_status(), None);
+    }
+
+    #[test]
+    fn test_expand_command_substitution_utf8() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            expand("$(printf 'caf\\303\\251')", &mut shell),
+            vec!["caf\u{e9}"]
+        );
+        assert_eq!(
+            expand("$(printf 'a\\377b')", &mut shell),
+            vec!["a\u{fffd}b"]
+        );
+    }
+
+    #[test]
+    fn test_expand_arithmetic() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("n", "7".to_string());
+        assert_eq!(expand("$((n % 4))", &mut shell), vec!["3"]);
+        assert_eq!(expand("$(( $n + 1 ))x", &mut shell), vec!["8x"]);
+        assert_eq!(expand("\"$((n += 1))\"", &mut shell), vec!["8"]);
+        assert_eq!(shell.vars().get("n"), Some("8"));
+        assert_eq!(
+            expand_word("$((1/0))", &mut shell),
+            Err("1/0: division by 0".to_string())
+        );
+        shell.vars_mut().set("V", "abcdef".to_string());
+        assert_eq!(expand("${V:n-6:1+1}", &mut shell), vec!["cd"]);
+    }
+
+    #[test]
+    fn test_expand_pathnames() {
+        let dir = std::env::temp_dir().join(format!("shell-expand-glob-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a.txt", "b.txt", "c.log"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        let dir = dir.display().to_string();
+        let mut shell = Shell::new();
+        shell.vars_mut().set("D", dir.clone());
+        assert_eq!(
+            expand("$D/*.txt", &mut shell),
+            vec![format!("{dir}/a.txt"), format!("{dir}/b.txt")]
+        );
+        assert_eq!(
+            expand("\"$D\"/?.log", &mut shell),
+            vec![format!("{dir}/c.log")]
+        );
+        assert_eq!(
+            expand("\"$D/*.txt\"", &mut shell),
+            vec![format!("{dir}/*.txt")]
+        );
+        assert_eq!(expand("$D/*.md", &mut shell), vec![format!("{dir}/*.md")]);
+        shell.options_mut().nullglob = true;
+        assert!(expand("$D/*.md", &mut shell).is_empty());
+        shell.options_mut().failglob = true;
+        assert_eq!(
+            expand_word("$D/*.md", &mut shell),
+            Err(format!("no match: {dir}/*.md"))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_expand_braces_before_parameters() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("x", "X".to_string());
+        assert_eq!(expand("${x}{1,2}", &mut shell), vec!["X1", "X2"]);
+        assert!(expand("$x{1,2}", &mut shell).is_empty());
+        assert_eq!(
+            expand("{a,$x}.{b,c}", &mut shell),
+            vec!["a.b", "a.c", "X.b", "X.c"]
+        );
+        assert_eq!(expand("\"{a,b}\"", &mut shell), vec!["{a,b}"]);
+        assert_eq!(
+            expand("{Z..a}", &mut shell),
+            vec!["Z", "[", "\\", "]", "^", "_", "`", "a"]
+        );
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("HOME", "/home/me".to_string());
+        shell.vars_mut().set("PWD", "/work".to_string());
+        shell.vars_mut().set("OLDPWD", "/old".to_string());
+        assert_eq!(expand("~", &mut shell), vec!["/home/me"]);
+        assert_eq!(expand("~/projects", &mut shell), vec!["/home/me/projects"]);
+        assert_eq!(expand("~+/a", &mut shell), vec!["/work/a"]);
+        assert_eq!(expand("~-", &mut shell), vec!["/old"]);
+        assert_eq!(
+            expand("~root", &mut shell),
+            vec![user_home("root").unwrap()]
+        );
+        assert_eq!(
+            expand("~no_such_user_x", &mut shell),
+            vec!["~no_such_user_x"]
+        );
+        assert_eq!(expand("'~'", &mut shell), vec!["~"]);
+        assert_eq!(expand("\"~\"", &mut shell), vec!["~"]);
+        assert_eq!(expand("a~", &mut shell), vec!["a~"]);
+        assert_eq!(expand("~'x'", &mut shell), vec!["~x"]);
+        assert_eq!(expand("${UNSET:-~}", &mut shell), vec!["/home/me"]);
+    }
+
+    #[test]
+    fn test_expand_tilde_in_assignments() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("HOME", "/h".to_string());
+        assert_eq!(
+            expand_assignment("~/bin:~/.local/bin", &mut shell),
+            Ok("/h/bin:/h/.local/bin".to_string())
+        );
+        assert_eq!(expand("PATH=~/bin:~", &mut shell), vec!["PATH=/h/bin:/h"]);
+        assert_eq!(expand("a:~", &mut shell), vec!["a:~"]);
+        assert_eq!(expand_string("x:~", &mut shell), Ok("x:~".to_string()));
+    }
+
+    #[test]
+    fn test_expand_field_splitting() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("v", "  a  b\tc\n".to_string());
+        assert_eq!(expand("$v", &mut shell), vec!["a", "b", "c"]);
+        assert_eq!(expand("\"$v\"", &mut shell), vec!["  a  b\tc\n"]);
+        assert_eq!(expand("x${v}y", &mut shell), vec!["x", "a", "b", "c", "y"]);
+        assert_eq!(expand("$(echo 1 2)", &mut shell), vec!["1", "2"]);
+        assert_eq!(expand("${UNSET:-p q}", &mut shell), vec!["p", "q"]);
+        shell.vars_mut().set("w", "   ".to_string());
+        assert!(expand("$w", &mut shell).is_empty());
+        assert_eq!(expand("''$w", &mut shell), vec![""]);
+    }
+
+    #[test]
+    fn test_expand_field_splitting_custom_ifs() {
+        let mut shell = Shell::new();
+        shell.vars_mut().set("IFS", ":".to_string());
+        shell.vars_mut().set("v", "a::b:".to_string());
+        assert_eq!(expand("$v", &mut shell), vec!["a", "", "b"]);
+        shell.vars_mut().set("IFS", " :".to_string());
+        shell.vars_mut().set("v", " a : b ::c ".to_string());
+        assert_eq!(expand("$v", &mut shell), vec!["a", "b", "", "c"]);
+        shell.vars_mut().set("IFS", String::new());
+        assert_eq!(expand("$v", &mut shell), vec![" a : b ::c "]);
+    }
+
+    #[test]
+    fn test_expand_positional_parameters() {
+        let mut shell = Shell::new();
+        shell.set_name("deploy.sh".to_string());
+        let params = ["a b", "", "c"].map(String::from).to_vec();
+        shell.set_positional(params);
+        assert_eq!(
+            expand_string("$0 $1 ${3} $# $4", &mut shell).unwrap(),
+            "deploy.sh a b c 3 "
+        );
+        assert_eq!(expand("$@", &mut shell), vec!["a", "b", "c"]);
+        assert_eq!(expand("$*", &mut shell), vec!["a", "b", "c"]);
+        assert_eq!(expand("\"$@\"", &mut shell), vec!["a b", "", "c"]);
+        assert_eq!(expand("\"x${@}y\"", &mut shell), vec!["xa b", "", "cy"]);
+        assert_eq!(expand("\"$*\"", &mut shell), vec!["a b  c"]);
+        shell.vars_mut().set("IFS", ",".to_string());
+        assert_eq!(expand("\"$*\"", &mut shell), vec!["a b,,c"]);
+        assert_eq!(expand_string("$@", &mut shell).unwrap(), "a b  c");
+        shell.set_positional(Vec::new());
+        assert!(expand("\"$@\"", &mut shell).is_empty());
+        assert_eq!(expand("\"$@\"''", &mut shell), vec![""]);
+        assert_eq!(expand("\"$*\"", &mut shell), vec![""]);
+        assert_eq!(expand("${@:-none}", &mut shell), vec!["none"]);
+    }
+
+    #[test]
+    fn test_expand_split_fields_are_globbed() {
+        let dir = std::env::temp_dir().join(format!("shell-expand-split-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("f.rs"), "").unwrap();
+        let dir = dir.display().to_string();
+        let mut shell = Shell::new();
+        shell.vars_mut().set("v", format!("{dir}/*.rs plain"));
+        assert_eq!(
+            expand("$v", &mut shell),
+            vec![format!("{dir}/f.rs"), "plain".to_string()]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,15 +1,20 @@
 use crate::cmd::Execute;
 use crate::shell::Shell;
+use crate::sys::error_message;
+use std::io::ErrorKind;
 use std::process::Command;
 use std::{env, path::Path};
 
+pub const COMMAND_NOT_EXECUTABLE: i32 = 126;
+pub const COMMAND_NOT_FOUND: i32 = 127;
+
 pub struct NonBuiltinCommand<'a> {
     name: &'a str,
 }
 
 impl<'a> NonBuiltinCommand<'a> {
-    pub fn new(name: &'a str) -> Result<Self, String> {
-        if external_command_exists(name) {
+    pub fn new(name: &'a str, shell: &Shell) -> Result<Self, String> {
+        if external_command_exists(name, shell) {
             Ok(NonBuiltinCommand { name })
         } else {
             Err(format!("{}: command not found", name))
@@ -18,27 +23,41 @@ impl<'a> NonBuiltinCommand<'a> {
 }
 
 impl<'a> Execute for NonBuiltinCommand<'a> {
-    fn execute(&self, args: &[String], shell: &mut Shell) -> Result<(), String> {
-        match Command::new(self.name).args(args).output() {
-            Ok(output) => {
-                print!("{}", String::from_utf8_lossy(&output.stdout));
+    fn execute(&self, args: &[String], shell: &mut Shell) -> Result<i32, String> {
+        let mut command = Command::new(self.name);
+        command.args(args).env_clear().envs(shell.vars().exported());
+        shell
+            .fds()
+            .configure(&mut command)
+            .map_err(|e| format!("{}: {e}", self.name))?;
+        match command.spawn() {
+            Ok(mut child) => {
+                let status = child.wait().map_err(|e| format!("{}: {e}", self.name))?;
                 shell
                     .history_mut()
                     .push(format!("{} {}", self.name, args.join(" ")));
-                Ok(())
+                Ok(shell.foreground_status(status))
+            }
+            Err(e) => {
+                shell.report_error(&format!("{}: {}", self.name, error_message(&e)));
+                Ok(if e.kind() == ErrorKind::NotFound {
+                    COMMAND_NOT_FOUND
+                } else {
+                    COMMAND_NOT_EXECUTABLE
+                })
             }
-            Err(_) => Err(format!("Unknown command: {}", self.name)),
         }
     }
 }
 
-pub fn external_command_exists(cmd: &str) -> bool {
+/// Searches the shell's own `PATH`, which may differ from the one it was started with.
+pub fn external_command_exists(cmd: &str, shell: &Shell) -> bool {
     if cmd.contains('/') {
         return Path::new(cmd).exists();
     }
 
-    if let Some(path_var) = env::var_os("PATH") {
-        for dir in env::split_paths(&path_var) {
+    if let Some(path_var) = shell.vars().get("PATH") {
+        for dir in env::split_paths(path_var) {
             let full = dir.join(cmd);
             if full.is_file() {
                 return true;
@@ -55,36 +74,44 @@ mod tests {
 
     #[test]
     fn test_non_builtin_command_new() {
-        let cmd = NonBuiltinCommand::new("ls").unwrap();
+        let cmd = NonBuiltinCommand::new("ls", &Shell::new()).unwrap();
         assert_eq!(cmd.name, "ls");
     }
 
     #[test]
     fn test_non_builtin_command_new_invalid() {
-        let result = NonBuiltinCommand::new("nonexistentcommand123456");
+        let result = NonBuiltinCommand::new("nonexistentcommand123456", &Shell::new());
         assert!(result.is_err());
     }
 
     #[test]
     fn test_non_builtin_command_empty_name() {
-        let result = NonBuiltinCommand::new("");
+        let result = NonBuiltinCommand::new("", &Shell::new());
         assert!(result.is_err());
     }
 
     #[test]
     fn test_non_builtin_command_execute_valid() {
-        let cmd = NonBuiltinCommand::new("echo").unwrap();
         let mut shell = Shell::new();
+        let cmd = NonBuiltinCommand::new("echo", &shell).unwrap();
         let args = vec!["hello".to_string()];
         let result = cmd.execute(&args, &mut shell);
-        assert!(result.is_ok());
+        assert_eq!(result, Ok(0));
         assert_eq!(shell.history().len(), 1);
     }
 
+    #[test]
+    fn test_non_builtin_command_execute_failure_status() {
+        let mut shell = Shell::new();
+        let cmd = NonBuiltinCommand::new("false", &shell).unwrap();
+        let result = cmd.execute(&[], &mut shell);
+        assert_eq!(result, Ok(1));
+    }
+
     #[test]
     fn test_non_builtin_command_execute_with_args() {
-        let cmd = NonBuiltinCommand::new("ls").unwrap();
         let mut shell = Shell::new();
+        let cmd = NonBuiltinCommand::new("ls", &shell).unwrap();
         let args = vec!["-la".to_string()];
         let result = cmd.execute(&args, &mut shell);
         assert!(result.is_ok());
@@ -93,7 +120,7 @@ mod tests {
 
     #[test]
     fn test_non_builtin_command_special_chars_in_name() {
-        let result = NonBuiltinCommand::new("cmd-with_dots");
+        let result = NonBuiltinCommand::new("cmd-with_dots", &Shell::new());
         // This will fail since command doesn't exist
         assert!(result.is_err());
     }
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+
+pub const STDIN: i32 = 0;
+pub const STDOUT: i32 = 1;
+pub const STDERR: i32 = 2;
+
+#[derive(Clone, Default)]
+pub struct FdTable {
+    fds: BTreeMap<i32, Rc<OwnedFd>>,
+}
+
+impl FdTable {
+    pub fn inherited() -> Self {
+        let mut table = FdTable::default();
+        for fd in [STDIN, STDOUT, STDERR] {
+            // SAFETY: the descriptor is only borrowed long enough to duplicate it.
+            let borrowed = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+            if let Ok(file) = borrowed.try_clone() {
+                table.set(fd, Rc::new(file.into()));
+            }
+        }
+        table
+    }
+
+    pub fn get(&self, fd: i32) -> Option<Rc<OwnedFd>> {
+        self.fds.get(&fd).cloned()
+    }
+
+    pub fn set(&mut self, fd: i32, target: Rc<OwnedFd>) {
+        self.fds.insert(fd, target);
+    }
+
+    pub fn close(&mut self, fd: i32) {
+        self.fds.remove(&fd);
+    }
+
+    pub fn stream(&self, fd: i32) -> Stream {
+        Stream(self.fds.get(&fd).cloned())
+    }
+
+    fn stdio(&self, fd: i32) -> io::Result<Stdio> {
+        match self.fds.get(&fd) {
+            Some(target) => Ok(target.try_clone()?.into()),
+            None => Ok(Stdio::null()),
+        }
+    }
+
+    pub fn configure(&self, command: &mut Command) -> io::Result<()> {
+        command
+            .stdin(self.stdio(STDIN)?)
+            .stdout(self.stdio(STDOUT)?)
+            .stderr(self.stdio(STDERR)?);
+
+        let mut extra: Vec<(i32, RawFd)> = self
+            .fds
+            .iter()
+            .filter(|&(&fd, _)| fd > STDERR)
+            .map(|(&fd, target)| (fd, target.as_raw_fd()))
+            .collect();
+        if !extra.is_empty() {
+            // The table entries stay open until spawn returns, so the raw fds are valid here.
+            let floor = extra.iter().map(|&(fd, _)| fd).max().unwrap_or(STDERR) + 1;
+            // SAFETY: the closure does not allocate and only calls fcntl and dup2.
+            unsafe {
+                command.pre_exec(move || move_fds(&mut extra, floor));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Moves every source out of the way before installing it, so that one target
+// never clobbers a source that a later entry still needs.
+fn move_fds(extra: &mut [(i32, RawFd)], floor: i32) -> io::Result<()> {
+    for (_, source) in extra.iter_mut() {
+        // SAFETY: fcntl with F_DUPFD_CLOEXEC only creates a new descriptor.
+        let copy = unsafe { libc::fcntl(*source, libc::F_DUPFD_CLOEXEC, floor) };
+        if copy == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        *source = copy;
+    }
+    for &(fd, copy) in extra.iter() {
+        // SAFETY: both descriptors are valid and dup2 leaves the copy to be closed on exec.
+        if unsafe { libc::dup2(copy, fd) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// A reader/writer over one entry of an `FdTable`; a closed entry fails with EBADF.
+pub struct Stream(Option<Rc<OwnedFd>>);
+
+impl Stream {
+    fn file(&self) -> io::Result<ManuallyDrop<File>> {
+        match &self.0 {
+            // SAFETY: the `Rc` keeps the descriptor open and ManuallyDrop never closes it.
+            Some(fd) => Ok(ManuallyDrop::new(unsafe {
+                File::from_raw_fd(fd.as_raw_fd())
+            })),
+            None => Err(io::Error::from_raw_os_error(libc::EBADF)),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file()?.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::pipe;
+
+    #[test]
+    fn test_inherited_has_standard_fds() {
+        let table = FdTable::inherited();
+        assert!(table.fds.contains_key(&STDOUT));
+        assert!(table.fds.contains_key(&STDERR));
+    }
+
+    #[test]
+    fn test_stream_round_trip() {
+        let (read, write) = pipe().unwrap();
+        let mut table = FdTable::default();
+        table.set(STDOUT, Rc::new(write));
+        table.set(STDIN, Rc::new(read));
+
+        table.stream(STDOUT).write_all(b"hello").unwrap();
+        table.fds.remove(&STDOUT);
+
+        let mut output = String::new();
+        table.stream(STDIN).read_to_string(&mut output).unwrap();
+        assert_eq!(output, "hello");
+    }
+
+    #[test]
+    fn test_closed_stream_fails() {
+        let table = FdTable::default();
+        let err = table.stream(STDOUT).write(b"x").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EBADF));
+    }
+}
@@ -0,0 +1,277 @@
+use crate::options::ShellOptions;
+use crate::pattern::{Pattern, PatternOptions, has_wildcards, unescape};
+use std::collections::HashSet;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/// Expands an escaped pattern against the filesystem, returning the sorted
+/// matching paths. No match gives an empty list.
+///
+/// With `globstar`, a `**` component matches any number of directories.
+pub fn glob(pattern: &str, options: &ShellOptions) -> Vec<String> {
+    let pattern_options = PatternOptions {
+        ignore_case: options.nocaseglob,
+        ..options.pattern_options()
+    };
+    let components = split_components(pattern);
+    let (root, components) = match components.split_first() {
+        Some((first, rest)) if first.is_empty() && !rest.is_empty() => ("/".to_string(), rest),
+        _ => (String::new(), components.as_slice()),
+    };
+    let mut paths = vec![root];
+    for (i, component) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        let mut next = Vec::new();
+        for base in &paths {
+            if component.is_empty() {
+                // A trailing or doubled slash keeps only directories.
+                if !base.is_empty() && is_dir(base) {
+                    next.push(format!("{base}/"));
+                }
+            } else if options.globstar && component == "**" {
+                if last {
+                    if !base.is_empty() && is_dir(base) {
+                        next.push(join(base, ""));
+                    }
+                } else {
+                    next.push(base.clone());
+                }
+                walk(base, !last, options, &mut HashSet::new(), &mut next);
+            } else if has_wildcards(component, pattern_options) {
+                for name in matching_entries(base, component, pattern_options, options) {
+                    let path = join(base, &name);
+                    if last || is_dir(&path) {
+                        next.push(path);
+                    }
+                }
+            } else {
+                next.push(join(base, &unescape(component)));
+            }
+        }
+        paths = next;
+    }
+    let mut matches: Vec<String> = paths
+        .into_iter()
+        .filter(|path| fs::symlink_metadata(path).is_ok())
+        .collect();
+    matches.sort();
+    matches.dedup();
+    matches
+}
+
+// Splits a pattern at each slash, which separates components even when quoted.
+fn split_components(pattern: &str) -> Vec<String> {
+    let mut components = vec![String::new()];
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('/') => components.push(String::new()),
+                Some(c) => {
+                    let component = components.last_mut().unwrap();
+                    component.push('\\');
+                    component.push(c);
+                }
+                None => components.last_mut().unwrap().push('\\'),
+            },
+            '/' => components.push(String::new()),
+            _ => components.last_mut().unwrap().push(c),
+        }
+    }
+    components
+}
+
+fn matching_entries(
+    dir: &str,
+    component: &str,
+    pattern_options: PatternOptions,
+    options: &ShellOptions,
+) -> Vec<String> {
+    let dir = if dir.is_empty() { "." } else { dir };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let pattern = Pattern::with_options(component, pattern_options);
+    // Hidden files only match a pattern that starts with a literal dot.
+    let show_hidden = options.dotglob || component.starts_with('.') || component.starts_with("\\.");
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| (show_hidden || !name.starts_with('.')) && pattern.matches(name))
+        .collect()
+}
+
+// Collects the paths below `dir` for `**`. Each directory is entered once,
+// so symbolic links that loop back to an ancestor are listed but not followed.
+fn walk(
+    dir: &str,
+    dirs_only: bool,
+    options: &ShellOptions,
+    visited: &mut HashSet<(u64, u64)>,
+    paths: &mut Vec<String>,
+) {
+    let dir_path = if dir.is_empty() { "." } else { dir };
+    let Ok(metadata) = fs::metadata(dir_path) else {
+        return;
+    };
+    if !visited.insert((metadata.dev(), metadata.ino())) {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir_path) else {
+        return;
+    };
+    let names = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| options.dotglob || !name.starts_with('.'));
+    for name in names {
+        let path = join(dir, &name);
+        let is_dir = is_dir(&path);
+        if is_dir || !dirs_only {
+            paths.push(path.clone());
+        }
+        if is_dir {
+            walk(&path, dirs_only, options, visited, paths);
+        }
+    }
+}
+
+fn join(base: &str, name: &str) -> String {
+    if base.is_empty() {
+        name.to_string()
+    } else if base.ends_with('/') {
+        format!("{base}{name}")
+    } else {
+        format!("{base}/{name}")
+    }
+}
+
+fn is_dir(path: &str) -> bool {
+    let path = if path.is_empty() { "." } else { path };
+    Path::new(path).is_dir()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    struct TempTree(PathBuf);
+
+    impl TempTree {
+        fn new(name: &str, files: &[&str]) -> Self {
+            let root = env::temp_dir().join(format!("shell-glob-{}-{name}", std::process::id()));
+            for file in files {
+                let path = root.join(file);
+                if file.ends_with('/') {
+                    fs::create_dir_all(path).unwrap();
+                } else {
+                    fs::create_dir_all(path.parent().unwrap()).unwrap();
+                    fs::write(path, "").unwrap();
+                }
+            }
+            TempTree(root)
+        }
+
+        fn glob(&self, pattern: &str, options: &ShellOptions) -> Vec<String> {
+            let root = self.0.display().to_string();
+            glob(&format!("{root}/{pattern}"), options)
+                .into_iter()
+                .map(|path| path[root.len() + 1..].to_string())
+                .collect()
+        }
+    }
+
+    impl Drop for TempTree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_glob_sorted_matches() {
+        let tree = TempTree::new("sorted", &["b.rs", "a.rs", "c.txt", "sub/d.rs"]);
+        let options = ShellOptions::default();
+        assert_eq!(tree.glob("*.rs", &options), vec!["a.rs", "b.rs"]);
+        assert_eq!(tree.glob("?.*", &options), vec!["a.rs", "b.rs", "c.txt"]);
+        assert_eq!(tree.glob("[ac].*", &options), vec!["a.rs", "c.txt"]);
+        assert_eq!(tree.glob("*/*.rs", &options), vec!["sub/d.rs"]);
+        assert_eq!(tree.glob("*/", &options), vec!["sub/"]);
+        assert!(tree.glob("*.md", &options).is_empty());
+    }
+
+    #[test]
+    fn test_glob_escaped_wildcards_are_literal() {
+        let tree = TempTree::new("escaped", &["*.rs", "a.rs"]);
+        let options = ShellOptions::default();
+        assert_eq!(tree.glob("\\*.rs", &options), vec!["*.rs"]);
+    }
+
+    #[test]
+    fn test_glob_hidden_files() {
+        let tree = TempTree::new("hidden", &[".hidden", "shown"]);
+        let mut options = ShellOptions::default();
+        assert_eq!(tree.glob("*", &options), vec!["shown"]);
+        assert_eq!(tree.glob(".*", &options), vec![".hidden"]);
+        options.dotglob = true;
+        assert_eq!(tree.glob("*", &options), vec![".hidden", "shown"]);
+    }
+
+    #[test]
+    fn test_glob_nocase() {
+        let tree = TempTree::new("nocase", &["README.md", "notes.MD"]);
+        let mut options = ShellOptions::default();
+        assert!(
+            tree.glob("*.md", &options)
+                .contains(&"README.md".to_string())
+        );
+        assert_eq!(tree.glob("*.md", &options).len(), 1);
+        options.nocaseglob = true;
+        assert_eq!(tree.glob("*.md", &options), vec!["README.md", "notes.MD"]);
+    }
+
+    #[test]
+    fn test_glob_globstar() {
+        let tree = TempTree::new(
+            "globstar",
+            &["a.rs", "src/b.rs", "src/x/c.rs", "src/x/d.txt"],
+        );
+        let mut options = ShellOptions::default();
+        assert_eq!(tree.glob("**/*.rs", &options), vec!["src/b.rs"]);
+        options.globstar = true;
+        assert_eq!(
+            tree.glob("**/*.rs", &options),
+            vec!["a.rs", "src/b.rs", "src/x/c.rs"]
+        );
+        assert_eq!(
+            tree.glob("src/**/*.rs", &options),
+            vec!["src/b.rs", "src/x/c.rs"]
+        );
+        assert_eq!(
+            tree.glob("src/**", &options),
+            vec!["src/", "src/b.rs", "src/x", "src/x/c.rs", "src/x/d.txt"]
+        );
+        assert_eq!(tree.glob("src/**/", &options), vec!["src/", "src/x/"]);
+    }
+
+    #[test]
+    fn test_glob_globstar_symlink_loop() {
+        let tree = TempTree::new("loop", &["d/f"]);
+        std::os::unix::fs::symlink(&tree.0, tree.0.join("d/up")).unwrap();
+        let options = ShellOptions {
+            globstar: true,
+            ..ShellOptions::default()
+        };
+        assert_eq!(tree.glob("**/f", &options), vec!["d/f"]);
+    }
+
+    #[test]
+    fn test_glob_extglob() {
+        let tree = TempTree::new("extglob", &["src/", "target/", "a.c", "b.h"]);
+        let mut options = ShellOptions::default();
+        assert!(tree.glob("!(target)", &options).is_empty());
+        options.extglob = true;
+        assert_eq!(tree.glob("!(target)", &options), vec!["a.c", "b.h", "src"]);
+        assert_eq!(tree.glob("*.@(c|h)", &options), vec!["a.c", "b.h"]);
+    }
+}
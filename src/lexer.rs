@@ -0,0 +1,937 @@
+use crate::parser::ParseError;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(String),
+    IoNumber(i32),
+    Newline,
+    Semi,
+    Amp,
+    AndIf,
+    Pipe,
+    OrIf,
+    DSemi,
+    SemiAnd,
+    DSemiAnd,
+    LParen,
+    RParen,
+    Less,
+    Great,
+    DGreat,
+    LessAnd,
+    GreatAnd,
+    LessGreat,
+    Clobber,
+    AndGreat,
+    AndDGreat,
+    TLess,
+    HereDoc(HereDoc),
+    /// The expression of a `((...))` arithmetic command.
+    Arithmetic(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HereDoc {
+    pub strip_tabs: bool,
+    pub quoted: bool,
+    pub body: String,
+}
+
+// Longest operators first so that matching can stop at the first hit.
+const OPERATORS: [(&str, Token); 20] = [
+    ("&>>", Token::AndDGreat),
+    ("<<<", Token::TLess),
+    (";;&", Token::DSemiAnd),
+    ("&&", Token::AndIf),
+    (";;", Token::DSemi),
+    (";&", Token::SemiAnd),
+    ("||", Token::OrIf),
+    (">>", Token::DGreat),
+    ("<&", Token::LessAnd),
+    (">&", Token::GreatAnd),
+    ("<>", Token::LessGreat),
+    (">|", Token::Clobber),
+    ("&>", Token::AndGreat),
+    ("|", Token::Pipe),
+    ("&", Token::Amp),
+    (";", Token::Semi),
+    ("(", Token::LParen),
+    (")", Token::RParen),
+    ("<", Token::Less),
+    (">", Token::Great),
+];
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{word}"),
+            Token::IoNumber(fd) => write!(f, "{fd}"),
+            Token::Newline => write!(f, "newline"),
+            Token::HereDoc(heredoc) if heredoc.strip_tabs => write!(f, "<<-"),
+            Token::HereDoc(_) => write!(f, "<<"),
+            Token::Arithmetic(_) => write!(f, "(("),
+            op => {
+                let text = OPERATORS
+                    .iter()
+                    .find(|(_, token)| token == op)
+                    .map_or("", |(text, _)| text);
+                write!(f, "{text}")
+            }
+        }
+    }
+}
+
+/// Splits `input` into tokens. Extglob groups such as `@(a|b)` are only read
+/// as part of a word when `extglob` is on, as in bash.
+pub fn tokenize(input: &str, extglob: bool) -> Result<Vec<Token>, ParseError> {
+    let mut lexer = Lexer::new(input, extglob);
+    lexer.run()?;
+    Ok(lexer.tokens)
+}
+
+/// Performs quote removal on a raw word as produced by the lexer.
+fn unquote(word: &str) -> String {
+    let mut result = String::new();
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            '\'' => result.extend(chars.by_ref().take_while(|&c| c != '\'')),
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(c @ ('$' | '`' | '"' | '\\')) => result.push(c),
+                            Some(c) => {
+                                result.push('\\');
+                                result.push(c);
+                            }
+                            None => result.push('\\'),
+                        },
+                        _ => result.push(c),
+                    }
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+// Reserved words after which a new command starts, so `((` opens arithmetic.
+const RESERVED_BEFORE_COMMAND: [&str; 9] = [
+    "!", "{", "if", "then", "elif", "else", "while", "until", "do",
+];
+
+fn is_metachar(c: char) -> bool {
+    matches!(
+        c,
+        ' ' | '\t' | '\n' | '|' | '&' | ';' | '(' | ')' | '<' | '>'
+    )
+}
+
+struct PendingHereDoc {
+    token: usize,
+    delimiter: String,
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    tokens: Vec<Token>,
+    pending: Vec<PendingHereDoc>,
+    extglob: bool,
+}
+
+impl Lexer {
+    fn new(input: &str, extglob: bool) -> Self {
+        Lexer {
+            chars: input.chars().collect(),
+            pos: 0,
+            tokens: Vec::new(),
+            pending: Vec::new(),
+            extglob,
+        }
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        self.chars[self.pos..]
+            .iter()
+            .take(text.chars().count())
+            .copied()
+            .eq(text.chars())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn run(&mut self) -> Result<(), ParseError> {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' => {
+                    self.pos += 1;
+                }
+                '\\' if self.peek_at(1) == Some('\n') => {
+                    self.pos += 2;
+                    if self.peek().is_none() {
+                        return Err(continued_past_end());
+                    }
+                }
+                '#' => self.skip_comment(),
+                '\n' => {
+                    self.pos += 1;
+                    self.tokens.push(Token::Newline);
+                    self.read_heredoc_bodies()?;
+                }
+                '<' if self.starts_with("<<") && !self.starts_with("<<<") => {
+                    self.read_heredoc_operator()?;
+                }
+                '<' | '>' if self.peek_at(1) == Some('(') => self.read_word()?,
+                '(' if self.starts_with("((") && (self.at_command_start() || self.after_for()) => {
+                    if !self.read_arithmetic()? {
+                        self.read_operator();
+                    }
+                }
+                '|' | '&' | ';' | '(' | ')' | '<' | '>' => self.read_operator(),
+                _ => self.read_word()?,
+            }
+        }
+        match self.pending.first() {
+            Some(heredoc) => Err(ParseError::Incomplete(format!(
+                "here-document delimited by end-of-file (wanted `{}')",
+                heredoc.delimiter
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn at_command_start(&self) -> bool {
+        match self.tokens.last() {
+            None
+            | Some(
+                Token::Newline
+                | Token::Semi
+                | Token::Amp
+                | Token::AndIf
+                | Token::OrIf
+                | Token::Pipe
+                | Token::LParen,
+            ) => true,
+            Some(Token::Word(word)) => RESERVED_BEFORE_COMMAND.contains(&word.as_str()),
+            _ => false,
+        }
+    }
+
+    // `for ((...))` opens a C-style loop header, read like an arithmetic command.
+    fn after_for(&self) -> bool {
+        matches!(self.tokens.last(), Some(Token::Word(word)) if word == "for")
+    }
+
+    // Reads `((expr))` as one token. If the parentheses do not close with `))`,
+    // nothing is consumed and the input is read as nested subshells instead.
+    fn read_arithmetic(&mut self) -> Result<bool, ParseError> {
+        let mut depth = 0;
+        let mut i = self.pos + 2;
+        loop {
+            match self.chars.get(i) {
+                Some('(') => depth += 1,
+                Some(')') if depth > 0 => depth -= 1,
+                Some(')') if self.chars.get(i + 1) == Some(&')') => break,
+                Some(')') => return Ok(false),
+                Some(_) => {}
+                None => return Err(unterminated(')')),
+            }
+            i += 1;
+        }
+        let expr = self.chars[self.pos + 2..i].iter().collect();
+        self.pos = i + 2;
+        self.tokens.push(Token::Arithmetic(expr));
+        Ok(true)
+    }
+
+    fn read_heredoc_operator(&mut self) -> Result<(), ParseError> {
+        self.pos += 2;
+        let strip_tabs = self.peek() == Some('-');
+        if strip_tabs {
+            self.pos += 1;
+        }
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+        let delimiter = match self.peek() {
+            Some(c) if !is_metachar(c) => self.scan_word()?,
+            Some('\n') | None => return Err(unexpected_token("newline")),
+            Some(c) => return Err(unexpected_token(&c.to_string())),
+        };
+        let quoted = delimiter.contains(['\'', '"', '\\']);
+        self.pending.push(PendingHereDoc {
+            token: self.tokens.len(),
+            delimiter: unquote(&delimiter),
+        });
+        self.tokens.push(Token::HereDoc(HereDoc {
+            strip_tabs,
+            quoted,
+            body: String::new(),
+        }));
+        Ok(())
+    }
+
+    // Here-document bodies start on the line after the operator, in the order
+    // the operators appeared, and each one runs up to its delimiter line.
+    fn read_heredoc_bodies(&mut self) -> Result<(), ParseError> {
+        while !self.pending.is_empty() {
+            let PendingHereDoc { token, delimiter } = &self.pending[0];
+            let Some(Token::HereDoc(heredoc)) = self.tokens.get(*token) else {
+                break;
+            };
+            let strip_tabs = heredoc.strip_tabs;
+            let mut body = String::new();
+            loop {
+                if self.pos >= self.chars.len() {
+                    return Ok(());
+                }
+                let end = self.chars[self.pos..]
+                    .iter()
+                    .position(|&c| c == '\n')
+                    .map_or(self.chars.len(), |i| self.pos + i);
+                let mut line: &[char] = &self.chars[self.pos..end];
+                if strip_tabs {
+                    while let ['\t', rest @ ..] = line {
+                        line = rest;
+                    }
+                }
+                let line: String = line.iter().collect();
+                if end == self.chars.len() && line != *delimiter {
+                    // The delimiter line may still be on its way.
+                    return Ok(());
+                }
+                self.pos = (end + 1).min(self.chars.len());
+                if line == *delimiter {
+                    break;
+                }
+                body.push_str(&line);
+                body.push('\n');
+            }
+            let pending = self.pending.remove(0);
+            if let Some(Token::HereDoc(heredoc)) = self.tokens.get_mut(pending.token) {
+                heredoc.body = body;
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_comment(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '\n' {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn read_operator(&mut self) {
+        for (text, token) in &OPERATORS {
+            if self.starts_with(text) {
+                self.pos += text.chars().count();
+                self.tokens.push(token.clone());
+                return;
+            }
+        }
+    }
+
+    fn read_word(&mut self) -> Result<(), ParseError> {
+        let word = self.scan_word()?;
+        let redirects_next = matches!(self.peek(), Some('<' | '>'));
+        match word.parse() {
+            Ok(fd) if redirects_next && word.chars().all(|c| c.is_ascii_digit()) => {
+                self.tokens.push(Token::IoNumber(fd));
+            }
+            _ => self.tokens.push(Token::Word(word)),
+        }
+        Ok(())
+    }
+
+    fn scan_word(&mut self) -> Result<String, ParseError> {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if matches!(c, '<' | '>') && self.peek_at(1) == Some('(') {
+                // Process substitution, which belongs to the word.
+                self.pos += 1;
+                word.push(c);
+                self.read_balanced(&mut word)?;
+                continue;
+            }
+            if is_metachar(c) {
+                break;
+            }
+            self.pos += 1;
+            match c {
+                '\\' => match self.bump() {
+                    // A backslash-newline pair is a line continuation and
+                    // disappears, once the line it continues onto is read.
+                    Some('\n') if self.peek().is_none() => return Err(continued_past_end()),
+                    Some('\n') => {}
+                    Some(c) => {
+                        word.push('\\');
+                        word.push(c);
+                    }
+                    None => word.push('\\'),
+                },
+                '\'' => self.read_single_quoted(&mut word)?,
+                '"' => self.read_double_quoted(&mut word)?,
+                '$' if self.peek() == Some('{') => self.read_braced(&mut word, false)?,
+                '$' if self.peek() == Some('(') => self.read_parenthesized(&mut word)?,
+                '`' => self.read_backquoted(&mut word)?,
+                '?' | '*' | '+' | '@' | '!' if self.extglob && self.peek() == Some('(') => {
+                    word.push(c);
+                    self.read_balanced(&mut word)?;
+                }
+                _ => word.push(c),
+            }
+        }
+        Ok(word)
+    }
+
+    fn read_single_quoted(&mut self, word: &mut String) -> Result<(), ParseError> {
+        word.push('\'');
+        loop {
+            match self.bump() {
+                Some('\'') => break,
+                Some(c) => word.push(c),
+                None => return Err(unterminated('\'')),
+            }
+        }
+        word.push('\'');
+        Ok(())
+    }
+
+    fn read_double_quoted(&mut self, word: &mut String) -> Result<(), ParseError> {
+        word.push('"');
+        loop {
+            match self.bump() {
+                Some('"') => break,
+                Some('\\') => match self.bump() {
+                    Some('\n') => {}
+                    Some(c) => {
+                        word.push('\\');
+                        word.push(c);
+                    }
+                    None => return Err(unterminated('"')),
+                },
+                Some('$') if self.peek() == Some('{') => self.read_braced(word, true)?,
+                Some('$') if self.peek() == Some('(') => self.read_parenthesized(word)?,
+                Some('`') => self.read_backquoted(word)?,
+                Some(c) => word.push(c),
+                None => return Err(unterminated('"')),
+            }
+        }
+        word.push('"');
+        Ok(())
+    }
+
+    // Reads a `${...}` expansion whose `$` was already consumed. Its contents may
+    // hold blanks, quotes and nested expansions, none of which end the word.
+    fn read_braced(&mut self, word: &mut String, in_quotes: bool) -> Result<(), ParseError> {
+        self.pos += 1;
+        word.push_str("${");
+        loop {
+            match self.bump() {
+                Some('}') => break,
+                Some('\\') => match self.bump() {
+                    Some('\n') => {}
+                    Some(c) => {
+                        word.push('\\');
+                        word.push(c);
+                    }
+                    None => return Err(unterminated('}')),
+                },
+                Some('\'') if !in_quotes => self.read_single_quoted(word)?,
+                Some('"') => self.read_double_quoted(word)?,
+                Some('$') if self.peek() == Some('{') => self.read_braced(word, in_quotes)?,
+                Some('$') if self.peek() == Some('(') => self.read_parenthesized(word)?,
+                Some('`') => self.read_backquoted(word)?,
+                Some(c) => word.push(c),
+                None => return Err(unterminated('}')),
+            }
+        }
+        word.push('}');
+        Ok(())
+    }
+
+    // Reads a `$(...)` command substitution whose `$` was already consumed,
+    // up to the parenthesis that balances the opening one.
+    fn read_parenthesized(&mut self, word: &mut String) -> Result<(), ParseError> {
+        word.push('$');
+        self.read_balanced(word)
+    }
+
+    // Reads from an opening parenthesis to the one that balances it, as in
+    // command substitutions and extended glob groups such as `@(a|b)`.
+    fn read_balanced(&mut self, word: &mut String) -> Result<(), ParseError> {
+        self.pos += 1;
+        word.push('(');
+        let mut depth = 0;
+        loop {
+            match self.bump() {
+                Some(')') if depth == 0 => break,
+                Some(c @ ('(' | ')')) => {
+                    depth += if c == '(' { 1 } else { -1 };
+                    word.push(c);
+                }
+                Some('\\') => {
+                    word.push('\\');
+                    word.push(self.bump().ok_or_else(|| unterminated(')'))?);
+                }
+                Some('\'') => self.read_single_quoted(word)?,
+                Some('"') => self.read_double_quoted(word)?,
+                Some('`') => self.read_backquoted(word)?,
+                Some(c) => word.push(c),
+                None => return Err(unterminated(')')),
+            }
+        }
+        word.push(')');
+        Ok(())
+    }
+
+    fn read_backquoted(&mut self, word: &mut String) -> Result<(), ParseError> {
+        word.push('`');
+        loop {
+            match self.bump() {
+                Some('`') => break,
+                Some('\\') => {
+                    word.push('\\');
+                    word.push(self.bump().ok_or_else(|| unterminated('`'))?);
+                }
+                Some(c) => word.push(c),
+                None => return Err(unterminated('`')),
+            }
+        }
+        word.push('`');
+        Ok(())
+    }
+}
+
+fn unterminated(quote: char) -> ParseError {
+    ParseError::Incomplete(format!(
+        "unexpected EOF while looking for matching `{quote}'"
+    ))
+}
+
+fn continued_past_end() -> ParseError {
+    ParseError::Incomplete("syntax error: unexpected end of file".to_string())
+}
+
+fn unexpected_token(token: &str) -> ParseError {
+    ParseError::Syntax(format!("syntax error near unexpected token `{token}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+        super::tokenize(input, true)
+    }
+
+    fn words(input: &str) -> Vec<String> {
+        tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|token| match token {
+                Token::Word(word) => unquote(&word),
+                other => panic!("unexpected token {other:?}"),
+            })
+            .collect()
+    }
+
+    fn word(text: &str) -> Token {
+        Token::Word(text.to_string())
+    }
+
+    #[test]
+    fn test_tokenize_single() {
+        assert_eq!(words("hello"), vec!["hello"]);
+    }
+
+    #[test]
+    fn test_tokenize_multiple() {
+        assert_eq!(words("hello world   test"), vec!["hello", "world", "test"]);
+    }
+
+    #[test]
+    fn test_tokenize_empty() {
+        assert!(tokenize("").unwrap().is_empty());
+        assert!(tokenize("   \t ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tokenize_double_quotes() {
+        assert_eq!(
+            words(r#"echo "hello   world""#),
+            vec!["echo", "hello   world"]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_single_quotes() {
+        assert_eq!(
+            words("git commit -m 'fix bug'"),
+            vec!["git", "commit", "-m", "fix bug"]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_single_quotes_keep_backslash() {
+        assert_eq!(words(r"echo 'a\nb'"), vec!["echo", r"a\nb"]);
+    }
+
+    #[test]
+    fn test_tokenize_backslash_escape() {
+        assert_eq!(words(r"ls my\ file"), vec!["ls", "my file"]);
+    }
+
+    #[test]
+    fn test_tokenize_escapes_in_double_quotes() {
+        assert_eq!(
+            words(r#"echo "a \"b\" \\ \$x \n""#),
+            vec!["echo", r#"a "b" \ $x \n"#]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_adjacent_quotes_join() {
+        assert_eq!(words(r#"a"b c"'d e'f"#), vec!["ab cd ef"]);
+    }
+
+    #[test]
+    fn test_tokenize_empty_quotes_make_word() {
+        assert_eq!(words(r#"echo "" ''"#), vec!["echo", "", ""]);
+    }
+
+    #[test]
+    fn test_tokenize_line_continuation() {
+        assert_eq!(words("echo a\\\nb"), vec!["echo", "ab"]);
+        assert_eq!(words("echo a \\\nb"), vec!["echo", "a", "b"]);
+        for input in ["echo a\\\n", "echo a \\\n"] {
+            assert!(matches!(tokenize(input), Err(ParseError::Incomplete(_))));
+        }
+    }
+
+    #[test]
+    fn test_tokenize_comment() {
+        assert_eq!(words("echo a#b # a comment"), vec!["echo", "a#b"]);
+    }
+
+    #[test]
+    fn test_tokenize_keeps_quotes_in_raw_word() {
+        let tokens = tokenize(r#"'a b'"c\"d"e\ f"#).unwrap();
+        assert_eq!(tokens, vec![word(r#"'a b'"c\"d"e\ f"#)]);
+    }
+
+    #[test]
+    fn test_tokenize_operators() {
+        let tokens = tokenize("a|b||c&&d;e&(f)\n").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                word("a"),
+                Token::Pipe,
+                word("b"),
+                Token::OrIf,
+                word("c"),
+                Token::AndIf,
+                word("d"),
+                Token::Semi,
+                word("e"),
+                Token::Amp,
+                Token::LParen,
+                word("f"),
+                Token::RParen,
+                Token::Newline,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_redirections() {
+        let tokens = tokenize("a<b >c >>d 2>&1 &>e &>>f <>g >|h <&-").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                word("a"),
+                Token::Less,
+                word("b"),
+                Token::Great,
+                word("c"),
+                Token::DGreat,
+                word("d"),
+                Token::IoNumber(2),
+                Token::GreatAnd,
+                word("1"),
+                Token::AndGreat,
+                word("e"),
+                Token::AndDGreat,
+                word("f"),
+                Token::LessGreat,
+                word("g"),
+                Token::Clobber,
+                word("h"),
+                Token::LessAnd,
+                word("-"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_io_number_needs_adjacent_operator() {
+        let tokens = tokenize("echo 2 >out 12>x").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                word("echo"),
+                word("2"),
+                Token::Great,
+                word("out"),
+                Token::IoNumber(12),
+                Token::Great,
+                word("x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_operator_display() {
+        assert_eq!(Token::AndDGreat.to_string(), "&>>");
+        assert_eq!(Token::GreatAnd.to_string(), ">&");
+        assert_eq!(Token::Newline.to_string(), "newline");
+    }
+
+    #[test]
+    fn test_tokenize_extglob_groups_are_words() {
+        assert_eq!(
+            words("ls !(target) *.@(c|h) x+(a b)y"),
+            vec!["ls", "!(target)", "*.@(c|h)", "x+(a b)y"]
+        );
+        assert!(tokenize("echo @(a").is_err());
+    }
+
+    #[test]
+    fn test_tokenize_extglob_groups_need_extglob() {
+        assert_eq!(
+            super::tokenize("ls !(target)", false).unwrap(),
+            vec![
+                word("ls"),
+                word("!"),
+                Token::LParen,
+                word("target"),
+                Token::RParen
+            ]
+        );
+        assert_eq!(
+            super::tokenize("echo *.@(c|h)", false).unwrap(),
+            vec![
+                word("echo"),
+                word("*.@"),
+                Token::LParen,
+                word("c"),
+                Token::Pipe,
+                word("h"),
+                Token::RParen
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_process_substitution() {
+        assert_eq!(
+            tokenize("diff <(sort a) <(echo ')')").unwrap(),
+            vec![word("diff"), word("<(sort a)"), word("<(echo ')')")]
+        );
+        assert_eq!(
+            tokenize("cat < <(ls)").unwrap(),
+            vec![word("cat"), Token::Less, word("<(ls)")]
+        );
+        assert_eq!(words("tee >(wc -l)"), vec!["tee", ">(wc -l)"]);
+    }
+
+    #[test]
+    fn test_tokenize_quoted_operators_are_words() {
+        assert_eq!(
+            words(r#"echo "a|b" 'c;d' e\&f"#),
+            vec!["echo", "a|b", "c;d", "e&f"]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_unterminated_single_quote() {
+        let err = tokenize("echo 'oops").unwrap_err();
+        assert!(matches!(err, ParseError::Incomplete(_)));
+        assert!(err.to_string().contains("matching `''"));
+    }
+
+    #[test]
+    fn test_tokenize_unterminated_double_quote() {
+        let err = tokenize("echo \"oops").unwrap_err();
+        assert!(matches!(err, ParseError::Incomplete(_)));
+        assert!(err.to_string().contains("matching `\"'"));
+    }
+
+    fn heredoc(strip_tabs: bool, quoted: bool, body: &str) -> Token {
+        Token::HereDoc(HereDoc {
+            strip_tabs,
+            quoted,
+            body: body.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_tokenize_heredoc() {
+        let tokens = tokenize("cat <<EOF | wc\nhello $x\n  world\nEOF\necho done").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                word("cat"),
+                heredoc(false, false, "hello $x\n  world\n"),
+                Token::Pipe,
+                word("wc"),
+                Token::Newline,
+                word("echo"),
+                word("done"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_heredoc_strip_tabs_and_quoted_delimiter() {
+        let tokens = tokenize("cat <<-'END'\n\t\tindented\n\tEND\n").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                word("cat"),
+                heredoc(true, true, "indented\n"),
+                Token::Newline,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_multiple_heredocs() {
+        let tokens = tokenize("cmd <<A 3<<B\none\nA\ntwo\nB").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                word("cmd"),
+                heredoc(false, false, "one\n"),
+                Token::IoNumber(3),
+                heredoc(false, false, "two\n"),
+                Token::Newline,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_heredoc_incomplete() {
+        let err = tokenize("cat <<EOF\nhello\n").unwrap_err();
+        assert!(matches!(err, ParseError::Incomplete(_)));
+        assert!(err.to_string().contains("wanted `EOF'"));
+        assert!(matches!(
+            tokenize("cat <<EOF").unwrap_err(),
+            ParseError::Incomplete(_)
+        ));
+    }
+
+    #[test]
+    fn test_tokenize_heredoc_missing_delimiter() {
+        let err = tokenize("cat <<\n").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::Syntax("syntax error near unexpected token `newline'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tokenize_here_string() {
+        let tokens = tokenize("cat <<< 'a b'").unwrap();
+        assert_eq!(tokens, vec![word("cat"), Token::TLess, word("'a b'")]);
+    }
+
+    #[test]
+    fn test_tokenize_braced_expansion() {
+        let tokens = tokenize("echo ${a} x${b:-one two}y \"${c:-\"q r\"}\"").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                word("echo"),
+                word("${a}"),
+                word("x${b:-one two}y"),
+                word("\"${c:-\"q r\"}\""),
+            ]
+        );
+        assert!(matches!(
+            tokenize("echo ${a").unwrap_err(),
+            ParseError::Incomplete(_)
+        ));
+    }
+
+    #[test]
+    fn test_tokenize_command_substitution() {
+        let tokens = tokenize("echo $(ls -l | wc) \"$(echo \")\" (x))\" `a b` x$(f $(g))").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                word("echo"),
+                word("$(ls -l | wc)"),
+                word("\"$(echo \")\" (x))\""),
+                word("`a b`"),
+                word("x$(f $(g))"),
+            ]
+        );
+        for input in ["echo $(ls", "echo `ls", "echo \"$(ls\""] {
+            assert!(
+                matches!(tokenize(input), Err(ParseError::Incomplete(_))),
+                "{input:?} should be incomplete"
+            );
+        }
+    }
+
+    #[test]
+    fn test_tokenize_arithmetic_command() {
+        assert_eq!(
+            tokenize("((x = 1 << 2)); ((y))").unwrap(),
+            vec![
+                Token::Arithmetic("x = 1 << 2".to_string()),
+                Token::Semi,
+                Token::Arithmetic("y".to_string()),
+            ]
+        );
+        assert_eq!(
+            tokenize("(( (a) + (b) ))").unwrap(),
+            vec![Token::Arithmetic(" (a) + (b) ".to_string())]
+        );
+        assert_eq!(
+            tokenize("echo ((x))").unwrap()[1..3],
+            [Token::LParen, Token::LParen]
+        );
+        assert_eq!(
+            tokenize("((echo a) )").unwrap()[..2],
+            [Token::LParen, Token::LParen]
+        );
+        assert!(matches!(
+            tokenize("((1 +").unwrap_err(),
+            ParseError::Incomplete(_)
+        ));
+    }
+}
@@ -1,12 +1,98 @@
+mod arith;
+mod ast;
+mod brace;
 mod builtin;
+mod cli;
 mod cmd;
+mod exec;
+mod expand;
 mod external;
+mod fds;
+mod glob;
 mod history;
+mod lexer;
+mod options;
+mod parser;
+mod pattern;
+mod redirect;
 mod shell;
+mod sys;
+mod vars;
 
-use shell::Shell;
+use cli::{Action, Invocation, Mode, USAGE, parse_args};
+use shell::{Control, Shell};
+use std::env;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::process;
+
+const RC_FILE: &str = ".shellrc";
+const SYSTEM_PROFILE: &str = "/etc/profile";
+const PROFILE: &str = ".profile";
 
 fn main() {
+    let argv: Vec<String> = env::args().collect();
+    let invocation = match parse_args(&argv) {
+        Ok(Action::Run(invocation)) => invocation,
+        Ok(Action::Help) => {
+            print!("{USAGE}");
+            return;
+        }
+        Ok(Action::Version) => {
+            println!("shell {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(e) => {
+            eprintln!("shell: {e}");
+            eprint!("{USAGE}");
+            process::exit(2);
+        }
+    };
+    process::exit(run(invocation) & 0xff);
+}
+
+// Sets the shell up as asked and runs it, returning its exit status.
+fn run(invocation: Invocation) -> i32 {
     let mut shell = Shell::new();
-    shell.run();
+    shell.set_name(invocation.name);
+    shell.set_positional(invocation.args);
+    shell.set_errexit(invocation.errexit);
+    shell.set_xtrace(invocation.xtrace);
+    let interactive = invocation.interactive
+        || (invocation.mode == Mode::Stdin
+            && io::stdin().is_terminal()
+            && io::stderr().is_terminal());
+    let home = env::home_dir();
+    let mut startup = Vec::new();
+    if invocation.login {
+        startup.push(PathBuf::from(SYSTEM_PROFILE));
+        startup.extend(home.map(|home| home.join(PROFILE)));
+    } else if interactive && !invocation.norc {
+        match (invocation.rcfile, home) {
+            (Some(rcfile), _) => startup.push(PathBuf::from(rcfile)),
+            (None, Some(home)) => startup.push(home.join(RC_FILE)),
+            (None, None) => {}
+        }
+    }
+    for path in startup.iter().filter(|path| path.is_file()) {
+        if let Err(e) = shell.source_file(&path.display().to_string()) {
+            shell.report_error(&e);
+        }
+        // An `exit` from `-e` ends the shell before it starts, as does a
+        // fatal error in a non-interactive one.
+        match shell.take_control() {
+            Some(Control::Exit(status)) => return status,
+            Some(Control::Abort(status)) if !interactive => return status,
+            _ => {}
+        }
+    }
+    match invocation.mode {
+        Mode::Command(source) => shell.run_string(&source, "-c"),
+        Mode::Script(path) => shell.run_script(&path),
+        Mode::Stdin if interactive => {
+            shell.run();
+            shell.last_status()
+        }
+        Mode::Stdin => shell.run_stdin(),
+    }
 }
@@ -0,0 +1,84 @@
+use crate::pattern::PatternOptions;
+
+/// Options toggled with `shopt`.
+#[derive(Debug, Clone, Default)]
+pub struct ShellOptions {
+    pub dotglob: bool,
+    pub extglob: bool,
+    pub failglob: bool,
+    pub globstar: bool,
+    pub nocaseglob: bool,
+    pub nullglob: bool,
+}
+
+impl ShellOptions {
+    pub const NAMES: [&str; 6] = [
+        "dotglob",
+        "extglob",
+        "failglob",
+        "globstar",
+        "nocaseglob",
+        "nullglob",
+    ];
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "dotglob" => Some(self.dotglob),
+            "extglob" => Some(self.extglob),
+            "failglob" => Some(self.failglob),
+            "globstar" => Some(self.globstar),
+            "nocaseglob" => Some(self.nocaseglob),
+            "nullglob" => Some(self.nullglob),
+            _ => None,
+        }
+    }
+
+    /// The options used to match patterns against text.
+    pub fn pattern_options(&self) -> PatternOptions {
+        PatternOptions {
+            ignore_case: false,
+            extglob: self.extglob,
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: bool) -> Result<(), String> {
+        let option = match name {
+            "dotglob" => &mut self.dotglob,
+            "extglob" => &mut self.extglob,
+            "failglob" => &mut self.failglob,
+            "globstar" => &mut self.globstar,
+            "nocaseglob" => &mut self.nocaseglob,
+            "nullglob" => &mut self.nullglob,
+            _ => return Err(format!("{name}: invalid shell option name")),
+        };
+        *option = value;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options_get_and_set() {
+        let mut options = ShellOptions::default();
+        assert_eq!(options.get("nullglob"), Some(false));
+        options.set("nullglob", true).unwrap();
+        assert!(options.nullglob);
+        assert_eq!(options.get("nullglob"), Some(true));
+        assert_eq!(options.get("bogus"), None);
+        assert_eq!(
+            options.set("bogus", true),
+            Err("bogus: invalid shell option name".to_string())
+        );
+    }
+
+    #[test]
+    fn test_options_names_are_known() {
+        let options = ShellOptions::default();
+        for name in ShellOptions::NAMES {
+            assert_eq!(options.get(name), Some(false));
+        }
+    }
+}
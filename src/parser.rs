@@ -0,0 +1,1024 @@
+use crate::ast::{
+    AndOr, Assignment, CaseItem, CaseTerminator, Command, CompoundCommand, Connector, List,
+    ListItem, Pipeline, Redirect, RedirectKind, SimpleCommand,
+};
+use crate::lexer::{Token, tokenize};
+use crate::vars::is_valid_name;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The input stopped in the middle of a command; more lines may complete it.
+    Incomplete(String),
+    Syntax(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Incomplete(message) | ParseError::Syntax(message) => {
+                write!(f, "{message}")
+            }
+        }
+    }
+}
+
+impl From<ParseError> for String {
+    fn from(err: ParseError) -> Self {
+        err.to_string()
+    }
+}
+
+pub fn parse(input: &str, extglob: bool) -> Result<List, ParseError> {
+    let tokens = tokenize(input, extglob)?;
+    let mut parser = Parser::new(tokens);
+    parser.skip_newlines();
+    let list = parser.parse_list()?;
+    match parser.peek() {
+        None => Ok(list),
+        Some(token) => Err(unexpected(token)),
+    }
+}
+
+// Reserved words that close a compound command; a list stops in front of them.
+const LIST_TERMINATORS: [&str; 8] = ["}", "then", "elif", "else", "fi", "do", "done", "esac"];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(tokens: Vec<Token>) -> Self {
+        Parser { tokens, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn bump(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned()?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn peek_reserved(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == word)
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Token::Newline) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect_reserved(&mut self, word: &str) -> Result<(), ParseError> {
+        if self.peek_reserved(word) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected_here())
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        if self.peek() == Some(&expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected_here())
+        }
+    }
+
+    fn unexpected_here(&self) -> ParseError {
+        match self.peek() {
+            Some(token) => unexpected(token),
+            None => unexpected_eof(),
+        }
+    }
+
+    fn at_list_end(&self) -> bool {
+        match self.peek() {
+            None | Some(Token::RParen | Token::DSemi | Token::SemiAnd | Token::DSemiAnd) => true,
+            Some(Token::Word(w)) => LIST_TERMINATORS.contains(&w.as_str()),
+            _ => false,
+        }
+    }
+
+    fn parse_list(&mut self) -> Result<List, ParseError> {
+        let mut list = List::default();
+        while !self.at_list_end() {
+            let and_or = self.parse_and_or()?;
+            let background = match self.peek() {
+                Some(Token::Amp) => true,
+                Some(Token::Semi | Token::Newline) => false,
+                _ => {
+                    list.items.push(ListItem {
+                        and_or,
+                        background: false,
+                    });
+                    break;
+                }
+            };
+            self.pos += 1;
+            self.skip_newlines();
+            list.items.push(ListItem { and_or, background });
+        }
+        Ok(list)
+    }
+
+    fn parse_compound_list(&mut self) -> Result<List, ParseError> {
+        self.skip_newlines();
+        let list = self.parse_list()?;
+        if list.items.is_empty() {
+            return Err(self.unexpected_here());
+        }
+        Ok(list)
+    }
+
+    fn parse_and_or(&mut self) -> Result<AndOr, ParseError> {
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let connector = match self.peek() {
+                Some(Token::AndIf) => Connector::And,
+                Some(Token::OrIf) => Connector::Or,
+                _ => break,
+            };
+            self.pos += 1;
+            self.skip_newlines();
+            rest.push((connector, self.parse_pipeline()?));
+        }
+        Ok(AndOr { first, rest })
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let negated = self.peek_reserved("!");
+        if negated {
+            self.pos += 1;
+        }
+        let mut commands = vec![self.parse_command()?];
+        while self.peek() == Some(&Token::Pipe) {
+            self.pos += 1;
+            self.skip_newlines();
+            commands.push(self.parse_command()?);
+        }
+        Ok(Pipeline { negated, commands })
+    }
+
+    fn parse_command(&mut self) -> Result<Command, ParseError> {
+        if self.peek_reserved("function") {
+            self.pos += 1;
+            return self.parse_function(true);
+        }
+        if let (Some(Token::Word(_)), Some(Token::LParen), Some(Token::RParen)) = (
+            self.peek(),
+            self.tokens.get(self.pos + 1),
+            self.tokens.get(self.pos + 2),
+        ) {
+            return self.parse_function(false);
+        }
+        let compound = match self.peek() {
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let list = self.parse_compound_list()?;
+                self.expect(Token::RParen)?;
+                CompoundCommand::Subshell(list)
+            }
+            Some(Token::Word(w)) if w == "{" => {
+                self.pos += 1;
+                let list = self.parse_compound_list()?;
+                self.expect_reserved("}")?;
+                CompoundCommand::BraceGroup(list)
+            }
+            Some(Token::Word(w)) if w == "if" => {
+                self.pos += 1;
+                self.parse_if()?
+            }
+            Some(Token::Word(w)) if w == "for" => {
+                self.pos += 1;
+                self.parse_for()?
+            }
+            Some(Token::Word(w)) if w == "case" => {
+                self.pos += 1;
+                self.parse_case()?
+            }
+            Some(Token::Word(w)) if w == "while" || w == "until" => {
+                let until = w == "until";
+                self.pos += 1;
+                let condition = self.parse_compound_list()?;
+                let body = self.parse_do_group()?;
+                CompoundCommand::While {
+                    condition,
+                    body,
+                    until,
+                }
+            }
+            Some(Token::Arithmetic(expr)) => {
+                let expr = expr.clone();
+                self.pos += 1;
+                CompoundCommand::Arithmetic(expr)
+            }
+            _ => return self.parse_simple_command().map(Command::Simple),
+        };
+        let mut redirects = Vec::new();
+        while let Some(redirect) = self.parse_redirect()? {
+            redirects.push(redirect);
+        }
+        Ok(Command::Compound(compound, redirects))
+    }
+
+    // Parses a definition from its name on, where the `()` may only be left
+    // out after the `function` keyword.
+    fn parse_function(&mut self, keyword: bool) -> Result<Command, ParseError> {
+        let name = match self.bump() {
+            Some(Token::Word(name)) if is_function_name(&name) => name,
+            Some(Token::Word(name)) => {
+                return Err(ParseError::Syntax(format!(
+                    "`{name}': not a valid identifier"
+                )));
+            }
+            Some(token) => return Err(unexpected(&token)),
+            None => return Err(unexpected_eof()),
+        };
+        if !keyword || self.peek() == Some(&Token::LParen) {
+            self.expect(Token::LParen)?;
+            self.expect(Token::RParen)?;
+        }
+        self.skip_newlines();
+        match self.parse_command()? {
+            body @ Command::Compound(..) => Ok(Command::FunctionDefinition {
+                name,
+                body: Rc::new(body),
+            }),
+            _ => Err(ParseError::Syntax(format!(
+                "syntax error: `{name}': function body must be a compound command"
+            ))),
+        }
+    }
+
+    fn parse_if(&mut self) -> Result<CompoundCommand, ParseError> {
+        let mut branches = Vec::new();
+        loop {
+            let condition = self.parse_compound_list()?;
+            self.expect_reserved("then")?;
+            let body = self.parse_compound_list()?;
+            branches.push((condition, body));
+            if !self.peek_reserved("elif") {
+                break;
+            }
+            self.pos += 1;
+        }
+        let else_body = if self.peek_reserved("else") {
+            self.pos += 1;
+            Some(self.parse_compound_list()?)
+        } else {
+            None
+        };
+        self.expect_reserved("fi")?;
+        Ok(CompoundCommand::If {
+            branches,
+            else_body,
+        })
+    }
+
+    fn parse_for(&mut self) -> Result<CompoundCommand, ParseError> {
+        if let Some(Token::Arithmetic(header)) = self.peek() {
+            let header = header.clone();
+            self.pos += 1;
+            let [init, condition, update] = header.split(';').collect::<Vec<_>>()[..] else {
+                return Err(ParseError::Syntax(format!(
+                    "syntax error: `(({header}))': expected three expressions"
+                )));
+            };
+            if self.peek() == Some(&Token::Semi) {
+                self.pos += 1;
+            }
+            self.skip_newlines();
+            return Ok(CompoundCommand::ArithmeticFor {
+                init: init.to_string(),
+                condition: condition.to_string(),
+                update: update.to_string(),
+                body: self.parse_do_group()?,
+            });
+        }
+        let name = match self.bump() {
+            Some(Token::Word(name)) if is_valid_name(&name) => name,
+            Some(Token::Word(name)) => {
+                return Err(ParseError::Syntax(format!(
+                    "`{name}': not a valid identifier"
+                )));
+            }
+            Some(token) => return Err(unexpected(&token)),
+            None => return Err(unexpected_eof()),
+        };
+        self.skip_newlines();
+        let words = if self.peek_reserved("in") {
+            self.pos += 1;
+            let mut words = Vec::new();
+            while let Some(Token::Word(word)) = self.peek() {
+                words.push(word.clone());
+                self.pos += 1;
+            }
+            match self.peek() {
+                Some(Token::Semi | Token::Newline) => self.pos += 1,
+                _ => return Err(self.unexpected_here()),
+            }
+            Some(words)
+        } else {
+            if self.peek() == Some(&Token::Semi) {
+                self.pos += 1;
+            }
+            None
+        };
+        self.skip_newlines();
+        let body = self.parse_do_group()?;
+        Ok(CompoundCommand::For { name, words, body })
+    }
+
+    fn parse_case(&mut self) -> Result<CompoundCommand, ParseError> {
+        let word = match self.bump() {
+            Some(Token::Word(word)) => word,
+            Some(token) => return Err(unexpected(&token)),
+            None => return Err(unexpected_eof()),
+        };
+        self.skip_newlines();
+        self.expect_reserved("in")?;
+        self.skip_newlines();
+        let mut items = Vec::new();
+        while !self.peek_reserved("esac") {
+            if self.peek() == Some(&Token::LParen) {
+                self.pos += 1;
+            }
+            let mut patterns = Vec::new();
+            loop {
+                match self.bump() {
+                    Some(Token::Word(pattern)) => patterns.push(pattern),
+                    Some(token) => return Err(unexpected(&token)),
+                    None => return Err(unexpected_eof()),
+                }
+                if self.peek() != Some(&Token::Pipe) {
+                    break;
+                }
+                self.pos += 1;
+            }
+            self.expect(Token::RParen)?;
+            self.skip_newlines();
+            let body = self.parse_list()?;
+            let terminator = match self.peek() {
+                Some(Token::DSemi) => CaseTerminator::Break,
+                Some(Token::SemiAnd) => CaseTerminator::FallThrough,
+                Some(Token::DSemiAnd) => CaseTerminator::Continue,
+                _ => {
+                    // The last clause may leave out its terminator.
+                    items.push(CaseItem {
+                        patterns,
+                        body,
+                        terminator: CaseTerminator::Break,
+                    });
+                    break;
+                }
+            };
+            self.pos += 1;
+            self.skip_newlines();
+            items.push(CaseItem {
+                patterns,
+                body,
+                terminator,
+            });
+        }
+        self.expect_reserved("esac")?;
+        Ok(CompoundCommand::Case { word, items })
+    }
+
+    fn parse_do_group(&mut self) -> Result<List, ParseError> {
+        self.expect_reserved("do")?;
+        let body = self.parse_compound_list()?;
+        self.expect_reserved("done")?;
+        Ok(body)
+    }
+
+    fn parse_simple_command(&mut self) -> Result<SimpleCommand, ParseError> {
+        let mut command = SimpleCommand::default();
+        loop {
+            if let Some(redirect) = self.parse_redirect()? {
+                command.redirects.push(redirect);
+            } else if let Some(Token::Word(word)) = self.peek() {
+                match assignment(word) {
+                    Some(assignment) if command.words.is_empty() => {
+                        command.assignments.push(assignment);
+                    }
+                    _ => command.words.push(word.clone()),
+                }
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        if command.assignments.is_empty()
+            && command.words.is_empty()
+            && command.redirects.is_empty()
+        {
+            return Err(self.unexpected_here());
+        }
+        Ok(command)
+    }
+
+    fn parse_redirect(&mut self) -> Result<Option<Redirect>, ParseError> {
+        let start = self.pos;
+        let io_number = match self.peek() {
+            Some(Token::IoNumber(fd)) => {
+                let fd = *fd;
+                self.pos += 1;
+                Some(fd)
+            }
+            _ => None,
+        };
+        let (default_fd, kind) = match self.peek() {
+            Some(Token::Less) => (0, RedirectKind::Input),
+            Some(Token::Great | Token::Clobber) => (1, RedirectKind::Output),
+            Some(Token::DGreat) => (1, RedirectKind::Append),
+            Some(Token::LessGreat) => (0, RedirectKind::ReadWrite),
+            Some(Token::LessAnd) => (0, RedirectKind::DupInput),
+            Some(Token::GreatAnd) => (1, RedirectKind::DupOutput),
+            Some(Token::AndGreat) if io_number.is_none() => (1, RedirectKind::OutputAll),
+            Some(Token::AndDGreat) if io_number.is_none() => (1, RedirectKind::AppendAll),
+            Some(Token::TLess) => (0, RedirectKind::HereString),
+            Some(Token::HereDoc(heredoc)) => {
+                let redirect = Redirect {
+                    fd: io_number.unwrap_or(0),
+                    kind: RedirectKind::HereDoc {
+                        expand: !heredoc.quoted,
+                    },
+                    target: heredoc.body.clone(),
+                };
+                self.pos += 1;
+                return Ok(Some(redirect));
+            }
+            _ => {
+                self.pos = start;
+                return Ok(None);
+            }
+        };
+        self.pos += 1;
+        match self.bump() {
+            Some(Token::Word(target)) => Ok(Some(Redirect {
+                fd: io_number.unwrap_or(default_fd),
+                kind,
+                target,
+            })),
+            Some(token) => Err(unexpected(&token)),
+            None => Err(unexpected_eof()),
+        }
+    }
+}
+
+fn unexpected(token: &Token) -> ParseError {
+    ParseError::Syntax(format!("syntax error near unexpected token `{token}'"))
+}
+
+fn unexpected_eof() -> ParseError {
+    ParseError::Incomplete("syntax error: unexpected end of file".to_string())
+}
+
+// A word is an assignment when everything before its first `=` is an unquoted name.
+fn assignment(word: &str) -> Option<Assignment> {
+    let (name, value) = word.split_once('=')?;
+    is_valid_name(name).then(|| Assignment {
+        name: name.to_string(),
+        value: value.to_string(),
+    })
+}
+
+// Function names may hold more than variable names, such as `-` and `.`, but
+// no quoting, expansions or `=`.
+fn is_function_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.chars().all(|c| c.is_ascii_digit())
+        && !name.contains(['\'', '"', '\\', '$', '`', '=', '{', '}'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<List, ParseError> {
+        super::parse(input, true)
+    }
+
+    fn simple(words: &[&str]) -> Command {
+        Command::Simple(SimpleCommand {
+            words: words.iter().map(|w| w.to_string()).collect(),
+            ..SimpleCommand::default()
+        })
+    }
+
+    fn redirect(fd: i32, kind: RedirectKind, target: &str) -> Redirect {
+        Redirect {
+            fd,
+            kind,
+            target: target.to_string(),
+        }
+    }
+
+    fn pipeline(commands: Vec<Command>) -> Pipeline {
+        Pipeline {
+            negated: false,
+            commands,
+        }
+    }
+
+    fn and_or(first: Pipeline) -> AndOr {
+        AndOr {
+            first,
+            rest: Vec::new(),
+        }
+    }
+
+    fn item(and_or: AndOr) -> ListItem {
+        ListItem {
+            and_or,
+            background: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        assert!(parse("").unwrap().items.is_empty());
+        assert!(parse("\n\n  # comment\n").unwrap().items.is_empty());
+    }
+
+    #[test]
+    fn test_parse_simple_command() {
+        let list = parse("echo 'hello world' foo").unwrap();
+        let expected = List {
+            items: vec![item(and_or(pipeline(vec![simple(&[
+                "echo",
+                "'hello world'",
+                "foo",
+            ])])))],
+        };
+        assert_eq!(list, expected);
+    }
+
+    #[test]
+    fn test_parse_pipeline() {
+        let list = parse("ls -l | grep foo |\n wc -l").unwrap();
+        let expected = pipeline(vec![
+            simple(&["ls", "-l"]),
+            simple(&["grep", "foo"]),
+            simple(&["wc", "-l"]),
+        ]);
+        assert_eq!(list.items[0].and_or.first, expected);
+    }
+
+    #[test]
+    fn test_parse_negated_pipeline() {
+        let list = parse("! grep foo").unwrap();
+        assert!(list.items[0].and_or.first.negated);
+    }
+
+    #[test]
+    fn test_parse_and_or() {
+        let list = parse("make && ./run || notify").unwrap();
+        let and_or = &list.items[0].and_or;
+        assert_eq!(and_or.first, pipeline(vec![simple(&["make"])]));
+        assert_eq!(
+            and_or.rest,
+            vec![
+                (Connector::And, pipeline(vec![simple(&["./run"])])),
+                (Connector::Or, pipeline(vec![simple(&["notify"])])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_sequential_and_background() {
+        let list = parse("a; b & c\nd").unwrap();
+        let backgrounds: Vec<bool> = list.items.iter().map(|i| i.background).collect();
+        assert_eq!(backgrounds, vec![false, true, false, false]);
+    }
+
+    #[test]
+    fn test_parse_subshell() {
+        let list = parse("(cd dir; make)").unwrap();
+        let expected = Command::Compound(
+            CompoundCommand::Subshell(List {
+                items: vec![
+                    item(and_or(pipeline(vec![simple(&["cd", "dir"])]))),
+                    item(and_or(pipeline(vec![simple(&["make"])]))),
+                ],
+            }),
+            Vec::new(),
+        );
+        assert_eq!(list.items[0].and_or.first.commands[0], expected);
+    }
+
+    #[test]
+    fn test_parse_brace_group() {
+        let list = parse("{ echo a; echo b; } | cat").unwrap();
+        let commands = &list.items[0].and_or.first.commands;
+        assert_eq!(commands.len(), 2);
+        let expected = Command::Compound(
+            CompoundCommand::BraceGroup(List {
+                items: vec![
+                    item(and_or(pipeline(vec![simple(&["echo", "a"])]))),
+                    item(and_or(pipeline(vec![simple(&["echo", "b"])]))),
+                ],
+            }),
+            Vec::new(),
+        );
+        assert_eq!(commands[0], expected);
+    }
+
+    #[test]
+    fn test_parse_quoted_brace_is_word() {
+        let list = parse("'{' echo }").unwrap();
+        let commands = &list.items[0].and_or.first.commands;
+        assert_eq!(commands[0], simple(&["'{'", "echo", "}"]));
+    }
+
+    #[test]
+    fn test_parse_redirects() {
+        let list = parse("cmd <in arg >out 2>>log 2>&1 3<&- &>all 4<>rw").unwrap();
+        let Command::Simple(command) = &list.items[0].and_or.first.commands[0] else {
+            panic!("expected a simple command");
+        };
+        assert_eq!(command.words, vec!["cmd", "arg"]);
+        assert_eq!(
+            command.redirects,
+            vec![
+                redirect(0, RedirectKind::Input, "in"),
+                redirect(1, RedirectKind::Output, "out"),
+                redirect(2, RedirectKind::Append, "log"),
+                redirect(2, RedirectKind::DupOutput, "1"),
+                redirect(3, RedirectKind::DupInput, "-"),
+                redirect(1, RedirectKind::OutputAll, "all"),
+                redirect(4, RedirectKind::ReadWrite, "rw"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_redirect_only_command() {
+        let list = parse("> out").unwrap();
+        let Command::Simple(command) = &list.items[0].and_or.first.commands[0] else {
+            panic!("expected a simple command");
+        };
+        assert!(command.words.is_empty());
+        assert_eq!(
+            command.redirects,
+            vec![redirect(1, RedirectKind::Output, "out")]
+        );
+    }
+
+    #[test]
+    fn test_parse_compound_redirects() {
+        let list = parse("{ echo a; } > out").unwrap();
+        let Command::Compound(_, redirects) = &list.items[0].and_or.first.commands[0] else {
+            panic!("expected a compound command");
+        };
+        assert_eq!(redirects, &vec![redirect(1, RedirectKind::Output, "out")]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse("| ls").unwrap_err().to_string(),
+            "syntax error near unexpected token `|'"
+        );
+        assert_eq!(
+            parse("ls &&").unwrap_err().to_string(),
+            "syntax error: unexpected end of file"
+        );
+        assert_eq!(
+            parse("echo a)").unwrap_err().to_string(),
+            "syntax error near unexpected token `)'"
+        );
+        assert!(parse("(echo a").is_err());
+        assert!(parse("{ echo a; ").is_err());
+        assert!(parse("( )").is_err());
+        assert!(parse("ls ;;").is_err());
+        assert_eq!(
+            parse("echo >").unwrap_err().to_string(),
+            "syntax error: unexpected end of file"
+        );
+        assert_eq!(
+            parse("echo > | cat").unwrap_err().to_string(),
+            "syntax error near unexpected token `|'"
+        );
+    }
+
+    #[test]
+    fn test_parse_incomplete_input() {
+        for input in [
+            "ls |",
+            "a &&\n",
+            "(echo a",
+            "{ echo a;",
+            "echo 'a",
+            "cat <<EOF\nx",
+        ] {
+            assert!(
+                matches!(parse(input), Err(ParseError::Incomplete(_))),
+                "{input:?} should be incomplete"
+            );
+        }
+        for input in ["| ls", "echo a)", "ls ;;"] {
+            assert!(
+                matches!(parse(input), Err(ParseError::Syntax(_))),
+                "{input:?} should be a syntax error"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_heredoc_redirects() {
+        let list = parse("cat <<EOF 3<<-'X' <<< \"$v\"\nbody\nEOF\n\tlit\n\tX\n").unwrap();
+        let Command::Simple(command) = &list.items[0].and_or.first.commands[0] else {
+            panic!("expected a simple command");
+        };
+        assert_eq!(
+            command.redirects,
+            vec![
+                redirect(0, RedirectKind::HereDoc { expand: true }, "body\n"),
+                redirect(3, RedirectKind::HereDoc { expand: false }, "lit\n"),
+                redirect(0, RedirectKind::HereString, "\"$v\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_assignments() {
+        let list = parse("A=1 B='x y' cmd C=2\nD=").unwrap();
+        let Command::Simple(command) = &list.items[0].and_or.first.commands[0] else {
+            panic!("expected a simple command");
+        };
+        let names: Vec<(&str, &str)> = command
+            .assignments
+            .iter()
+            .map(|a| (a.name.as_str(), a.value.as_str()))
+            .collect();
+        assert_eq!(names, vec![("A", "1"), ("B", "'x y'")]);
+        assert_eq!(command.words, vec!["cmd", "C=2"]);
+
+        let Command::Simple(command) = &list.items[1].and_or.first.commands[0] else {
+            panic!("expected a simple command");
+        };
+        assert_eq!(command.assignments.len(), 1);
+        assert!(command.words.is_empty());
+
+        let list = parse("'A'=1 1A=2").unwrap();
+        assert_eq!(
+            list.items[0].and_or.first.commands[0],
+            simple(&["'A'=1", "1A=2"])
+        );
+    }
+
+    #[test]
+    fn test_parse_arithmetic_command() {
+        let list = parse("((i++)) > /dev/null && echo ok").unwrap();
+        let and_or = &list.items[0].and_or;
+        assert_eq!(
+            and_or.first.commands[0],
+            Command::Compound(
+                CompoundCommand::Arithmetic("i++".to_string()),
+                vec![redirect(1, RedirectKind::Output, "/dev/null")]
+            )
+        );
+        assert_eq!(and_or.rest.len(), 1);
+    }
+
+    fn list(commands: &[&[&str]]) -> List {
+        List {
+            items: commands
+                .iter()
+                .map(|words| item(and_or(pipeline(vec![simple(words)]))))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_if() {
+        let input = "if a; then b; elif c\nthen d\nelse e; f; fi > out";
+        let parsed = parse(input).unwrap();
+        assert_eq!(
+            parsed.items[0].and_or.first.commands[0],
+            Command::Compound(
+                CompoundCommand::If {
+                    branches: vec![
+                        (list(&[&["a"]]), list(&[&["b"]])),
+                        (list(&[&["c"]]), list(&[&["d"]])),
+                    ],
+                    else_body: Some(list(&[&["e"], &["f"]])),
+                },
+                vec![redirect(1, RedirectKind::Output, "out")]
+            )
+        );
+        let parsed = parse("if a; then echo fi then; fi").unwrap();
+        let Command::Compound(CompoundCommand::If { branches, .. }, _) =
+            &parsed.items[0].and_or.first.commands[0]
+        else {
+            panic!("expected an if command");
+        };
+        assert_eq!(branches[0].1, list(&[&["echo", "fi", "then"]]));
+    }
+
+    #[test]
+    fn test_parse_if_errors() {
+        for input in [
+            "if a; then b",
+            "if a\n",
+            "if a; then b; else",
+            "if a; then b; elif c;",
+        ] {
+            assert!(
+                matches!(parse(input), Err(ParseError::Incomplete(_))),
+                "{input:?} should be incomplete"
+            );
+        }
+        assert_eq!(
+            parse("if a; fi").unwrap_err().to_string(),
+            "syntax error near unexpected token `fi'"
+        );
+        assert_eq!(
+            parse("if a; then fi").unwrap_err().to_string(),
+            "syntax error near unexpected token `fi'"
+        );
+        assert!(matches!(parse("then"), Err(ParseError::Syntax(_))));
+    }
+
+    #[test]
+    fn test_parse_loops() {
+        let parsed = parse("for x in a 'b'; do c; done").unwrap();
+        assert_eq!(
+            parsed.items[0].and_or.first.commands[0],
+            Command::Compound(
+                CompoundCommand::For {
+                    name: "x".to_string(),
+                    words: Some(vec!["a".to_string(), "'b'".to_string()]),
+                    body: list(&[&["c"]]),
+                },
+                Vec::new()
+            )
+        );
+        let parsed = parse("for x\ndo c; done").unwrap();
+        assert!(matches!(
+            &parsed.items[0].and_or.first.commands[0],
+            Command::Compound(CompoundCommand::For { words: None, .. }, _)
+        ));
+        let parsed = parse("for ((i = 0; i < 2; i++)); do c; done").unwrap();
+        assert_eq!(
+            parsed.items[0].and_or.first.commands[0],
+            Command::Compound(
+                CompoundCommand::ArithmeticFor {
+                    init: "i = 0".to_string(),
+                    condition: " i < 2".to_string(),
+                    update: " i++".to_string(),
+                    body: list(&[&["c"]]),
+                },
+                Vec::new()
+            )
+        );
+        let parsed = parse("until a; do b; done").unwrap();
+        assert_eq!(
+            parsed.items[0].and_or.first.commands[0],
+            Command::Compound(
+                CompoundCommand::While {
+                    condition: list(&[&["a"]]),
+                    body: list(&[&["b"]]),
+                    until: true,
+                },
+                Vec::new()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_loop_errors() {
+        for input in [
+            "for x in a",
+            "for x in a; do",
+            "while a; do b;",
+            "for ((;;))",
+        ] {
+            assert!(
+                matches!(parse(input), Err(ParseError::Incomplete(_))),
+                "{input:?} should be incomplete"
+            );
+        }
+        assert_eq!(
+            parse("for 1x in a; do b; done").unwrap_err().to_string(),
+            "`1x': not a valid identifier"
+        );
+        assert!(matches!(
+            parse("for ((i)); do b; done"),
+            Err(ParseError::Syntax(_))
+        ));
+        assert_eq!(
+            parse("while a; done").unwrap_err().to_string(),
+            "syntax error near unexpected token `done'"
+        );
+    }
+
+    #[test]
+    fn test_parse_case() {
+        let parsed =
+            parse("case $1 in\n  start|run) a ;;\n  (stop) ;&\n  *) b; c ;;&\n  x) d\nesac")
+                .unwrap();
+        let item = |patterns: &[&str], body: List, terminator| CaseItem {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            body,
+            terminator,
+        };
+        assert_eq!(
+            parsed.items[0].and_or.first.commands[0],
+            Command::Compound(
+                CompoundCommand::Case {
+                    word: "$1".to_string(),
+                    items: vec![
+                        item(&["start", "run"], list(&[&["a"]]), CaseTerminator::Break),
+                        item(&["stop"], List::default(), CaseTerminator::FallThrough),
+                        item(&["*"], list(&[&["b"], &["c"]]), CaseTerminator::Continue),
+                        item(&["x"], list(&[&["d"]]), CaseTerminator::Break),
+                    ],
+                },
+                Vec::new()
+            )
+        );
+        assert!(parse("case x in esac").is_ok());
+    }
+
+    #[test]
+    fn test_parse_case_errors() {
+        for input in ["case x", "case x in", "case x in a) b;;", "case x in a|"] {
+            assert!(
+                matches!(parse(input), Err(ParseError::Incomplete(_))),
+                "{input:?} should be incomplete"
+            );
+        }
+        assert_eq!(
+            parse("case x in a b) c;; esac").unwrap_err().to_string(),
+            "syntax error near unexpected token `b'"
+        );
+        assert_eq!(
+            parse("echo a;;").unwrap_err().to_string(),
+            "syntax error near unexpected token `;;'"
+        );
+    }
+
+    #[test]
+    fn test_parse_function_definitions() {
+        let body = |redirects| {
+            Rc::new(Command::Compound(
+                CompoundCommand::BraceGroup(list(&[&["echo", "$1"]])),
+                redirects,
+            ))
+        };
+        for input in [
+            "greet() { echo $1; }",
+            "greet ()\n{\n  echo $1\n}",
+            "function greet { echo $1; }",
+            "function greet() { echo $1; }",
+        ] {
+            assert_eq!(
+                parse(input).unwrap().items[0].and_or.first.commands[0],
+                Command::FunctionDefinition {
+                    name: "greet".to_string(),
+                    body: body(Vec::new()),
+                },
+                "{input:?}"
+            );
+        }
+        assert_eq!(
+            parse("greet() { echo $1; } >&2").unwrap().items[0]
+                .and_or
+                .first
+                .commands[0],
+            Command::FunctionDefinition {
+                name: "greet".to_string(),
+                body: body(vec![redirect(1, RedirectKind::DupOutput, "2")]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_function_errors() {
+        assert!(matches!(parse("f()"), Err(ParseError::Incomplete(_))));
+        assert!(matches!(
+            parse("function f"),
+            Err(ParseError::Incomplete(_))
+        ));
+        assert_eq!(
+            parse("f() echo hi").unwrap_err().to_string(),
+            "syntax error: `f': function body must be a compound command"
+        );
+        assert_eq!(
+            parse("'f'() { :; }").unwrap_err().to_string(),
+            "`'f'': not a valid identifier"
+        );
+        assert!(parse("{ (echo a); }").is_ok());
+    }
+}
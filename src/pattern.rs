@@ -0,0 +1,408 @@
+//! Shell pattern matching, shared by parameter expansion and filename globbing.
+//!
+//! Patterns use `*`, `?` and bracket expressions; a backslash makes the next
+//! character literal, which is how quoted parts of a word reach the matcher.
+//! With `extglob`, `?(..)`, `*(..)`, `+(..)`, `@(..)` and `!(..)` match
+//! `|`-separated alternatives.
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(char),
+    AnyChar,
+    AnyString,
+    Bracket(Bracket),
+    Group(GroupKind, Vec<Vec<Token>>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GroupKind {
+    ZeroOrOne,
+    ZeroOrMore,
+    OneOrMore,
+    ExactlyOne,
+    Not,
+}
+
+impl GroupKind {
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            '?' => Some(GroupKind::ZeroOrOne),
+            '*' => Some(GroupKind::ZeroOrMore),
+            '+' => Some(GroupKind::OneOrMore),
+            '@' => Some(GroupKind::ExactlyOne),
+            '!' => Some(GroupKind::Not),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Bracket {
+    negated: bool,
+    items: Vec<BracketItem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BracketItem {
+    Char(char),
+    Range(char, char),
+    Class(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PatternOptions {
+    pub ignore_case: bool,
+    pub extglob: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+    options: PatternOptions,
+}
+
+impl Pattern {
+    pub fn with_options(pattern: &str, options: PatternOptions) -> Self {
+        let chars: Vec<char> = pattern.chars().collect();
+        let tokens = parse_tokens(&chars, options);
+        Pattern { tokens, options }
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        self.match_tokens(&self.tokens, &text)
+    }
+
+    fn match_tokens(&self, tokens: &[Token], text: &[char]) -> bool {
+        match tokens.split_first() {
+            None => text.is_empty(),
+            Some((Token::AnyString, rest)) => {
+                (0..=text.len()).any(|i| self.match_tokens(rest, &text[i..]))
+            }
+            Some((Token::Group(kind, alternatives), rest)) => (0..=text.len()).any(|i| {
+                self.match_group(*kind, alternatives, &text[..i])
+                    && self.match_tokens(rest, &text[i..])
+            }),
+            Some((token, rest)) => match text.split_first() {
+                Some((&c, tail)) => self.match_char(token, c) && self.match_tokens(rest, tail),
+                None => false,
+            },
+        }
+    }
+
+    fn match_char(&self, token: &Token, c: char) -> bool {
+        let ignore_case = self.options.ignore_case;
+        match token {
+            Token::Literal(x) if ignore_case => x.to_lowercase().eq(c.to_lowercase()),
+            Token::Literal(x) => *x == c,
+            Token::AnyChar => true,
+            Token::Bracket(bracket) => bracket.matches(c, ignore_case),
+            Token::AnyString | Token::Group(..) => unreachable!(),
+        }
+    }
+
+    fn match_group(&self, kind: GroupKind, alternatives: &[Vec<Token>], text: &[char]) -> bool {
+        let one = |text: &[char]| alternatives.iter().any(|alt| self.match_tokens(alt, text));
+        match kind {
+            GroupKind::ExactlyOne => one(text),
+            GroupKind::ZeroOrOne => text.is_empty() || one(text),
+            GroupKind::ZeroOrMore if text.is_empty() => true,
+            GroupKind::ZeroOrMore | GroupKind::OneOrMore => {
+                // Each repetition consumes at least one character, so this terminates.
+                one(text)
+                    || (1..text.len()).any(|i| {
+                        one(&text[..i])
+                            && self.match_group(GroupKind::OneOrMore, alternatives, &text[i..])
+                    })
+            }
+            GroupKind::Not => !one(text),
+        }
+    }
+}
+
+fn parse_tokens(chars: &[char], options: PatternOptions) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if options.extglob
+            && chars.get(i + 1) == Some(&'(')
+            && let Some(kind) = GroupKind::from_char(chars[i])
+            && let Some((alternatives, len)) = parse_group(&chars[i + 2..], options)
+        {
+            tokens.push(Token::Group(kind, alternatives));
+            i += len + 2;
+            continue;
+        }
+        let token = match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                Token::Literal(chars[i])
+            }
+            '?' => Token::AnyChar,
+            '*' if tokens.last() == Some(&Token::AnyString) => {
+                i += 1;
+                continue;
+            }
+            '*' => Token::AnyString,
+            '[' => match parse_bracket(&chars[i + 1..]) {
+                Some((bracket, len)) => {
+                    i += len;
+                    Token::Bracket(bracket)
+                }
+                None => Token::Literal('['),
+            },
+            c => Token::Literal(c),
+        };
+        tokens.push(token);
+        i += 1;
+    }
+    tokens
+}
+
+// Parses the alternatives of an extended glob group after its `(`, returning
+// them with the number of characters consumed up to and including the `)`.
+fn parse_group(chars: &[char], options: PatternOptions) -> Option<(Vec<Vec<Token>>, usize)> {
+    let mut alternatives = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    let mut i = 0;
+    loop {
+        match *chars.get(i)? {
+            '\\' => i += 1,
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            c @ ('|' | ')') if depth == 0 => {
+                alternatives.push(parse_tokens(&chars[start..i], options));
+                start = i + 1;
+                if c == ')' {
+                    return Some((alternatives, i + 1));
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// Reports whether an escaped pattern has any unescaped wildcard characters.
+pub fn has_wildcards(pattern: &str, options: PatternOptions) -> bool {
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            '+' | '@' | '!' if options.extglob && chars.peek() == Some(&'(') => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Removes the escaping from a pattern that has no wildcards.
+pub fn unescape(pattern: &str) -> String {
+    let mut result = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+// Parses the body of a bracket expression after its `[`, returning it along
+// with the number of characters consumed up to and including the `]`.
+fn parse_bracket(chars: &[char]) -> Option<(Bracket, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let mut items = Vec::new();
+    let start = i;
+    loop {
+        let c = *chars.get(i)?;
+        match c {
+            ']' if i > start => return Some((Bracket { negated, items }, i + 1)),
+            '[' if chars.get(i + 1) == Some(&':') => {
+                let rest = &chars[i + 2..];
+                let end = rest.windows(2).position(|w| w == [':', ']'])?;
+                items.push(BracketItem::Class(rest[..end].iter().collect()));
+                i += end + 4;
+                continue;
+            }
+            _ => {}
+        }
+        let c = if c == '\\' {
+            i += 1;
+            *chars.get(i)?
+        } else {
+            c
+        };
+        match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some('-'), Some(&end)) if end != ']' => {
+                items.push(BracketItem::Range(c, end));
+                i += 3;
+            }
+            _ => {
+                items.push(BracketItem::Char(c));
+                i += 1;
+            }
+        }
+    }
+}
+
+impl Bracket {
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let contains = |c: char| {
+            self.items.iter().any(|item| match item {
+                BracketItem::Char(x) => *x == c,
+                BracketItem::Range(start, end) => (*start..=*end).contains(&c),
+                BracketItem::Class(class) => class_matches(class, c),
+            })
+        };
+        let found = if ignore_case {
+            contains(c) || c.to_lowercase().any(contains) || c.to_uppercase().any(contains)
+        } else {
+            contains(c)
+        };
+        found != self.negated
+    }
+}
+
+fn class_matches(class: &str, c: char) -> bool {
+    match class {
+        "alnum" => c.is_alphanumeric(),
+        "alpha" => c.is_alphabetic(),
+        "blank" => c == ' ' || c == '\t',
+        "cntrl" => c.is_control(),
+        "digit" => c.is_ascii_digit(),
+        "graph" => c.is_ascii_graphic(),
+        "lower" => c.is_lowercase(),
+        "print" => c.is_ascii_graphic() || c == ' ',
+        "punct" => c.is_ascii_punctuation(),
+        "space" => c.is_whitespace(),
+        "upper" => c.is_uppercase(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::with_options(pattern, PatternOptions::default()).matches(text)
+    }
+
+    #[test]
+    fn test_pattern_literal() {
+        assert!(matches("abc", "abc"));
+        assert!(!matches("abc", "abcd"));
+        assert!(matches("", ""));
+    }
+
+    #[test]
+    fn test_pattern_wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rc"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "ac"));
+        assert!(matches("*", ""));
+        assert!(matches("a**b*", "axxbyy"));
+    }
+
+    #[test]
+    fn test_pattern_brackets() {
+        assert!(matches("[abc]x", "bx"));
+        assert!(!matches("[abc]x", "dx"));
+        assert!(matches("[a-c][!0-9]", "bz"));
+        assert!(!matches("[a-c][^0-9]", "b5"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[[:digit:]][[:upper:]]", "1A"));
+        assert!(!matches("[[:alpha:]]", "1"));
+    }
+
+    #[test]
+    fn test_pattern_unterminated_bracket_is_literal() {
+        assert!(matches("[ab", "[ab"));
+        assert!(!matches("[ab", "a"));
+    }
+
+    #[test]
+    fn test_pattern_escapes() {
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "x"));
+        assert!(matches("a\\?", "a?"));
+        assert!(matches("[\\]]", "]"));
+    }
+
+    #[test]
+    fn test_pattern_ignore_case() {
+        let options = PatternOptions {
+            ignore_case: true,
+            ..PatternOptions::default()
+        };
+        let pattern = Pattern::with_options("*.TXT", options);
+        assert!(pattern.matches("notes.txt"));
+        assert!(Pattern::with_options("[a-c]x", options).matches("BX"));
+        assert!(!Pattern::with_options("[!a]", options).matches("A"));
+        assert!(!matches("*.TXT", "notes.txt"));
+    }
+
+    #[test]
+    fn test_has_wildcards_and_unescape() {
+        let options = PatternOptions::default();
+        assert!(has_wildcards("*.rs", options));
+        assert!(has_wildcards("a[bc]", options));
+        assert!(!has_wildcards("\\*.rs", options));
+        assert!(!has_wildcards("plain", options));
+        assert!(!has_wildcards("@(a|b)", options));
+        let extglob = PatternOptions {
+            extglob: true,
+            ..options
+        };
+        assert!(has_wildcards("@(a|b)", extglob));
+        assert!(!has_wildcards("\\@(a|b)", extglob));
+        assert_eq!(unescape("a\\*b\\\\"), "a*b\\");
+    }
+
+    fn ext_matches(pattern: &str, text: &str) -> bool {
+        let options = PatternOptions {
+            extglob: true,
+            ..PatternOptions::default()
+        };
+        Pattern::with_options(pattern, options).matches(text)
+    }
+
+    #[test]
+    fn test_pattern_extglob() {
+        assert!(ext_matches("@(a|bc).rs", "bc.rs"));
+        assert!(!ext_matches("@(a|bc).rs", "abc.rs"));
+        assert!(ext_matches("x?(y)z", "xz"));
+        assert!(ext_matches("x?(y)z", "xyz"));
+        assert!(!ext_matches("x?(y)z", "xyyz"));
+        assert!(ext_matches("x*(ab)z", "xz"));
+        assert!(ext_matches("x*(ab)z", "xababz"));
+        assert!(!ext_matches("x+(ab)z", "xz"));
+        assert!(ext_matches("x+(ab|c)z", "xabcabz"));
+        assert!(ext_matches("!(target)", "src"));
+        assert!(!ext_matches("!(target)", "target"));
+        assert!(ext_matches("!(*.o)", "main.c"));
+        assert!(!ext_matches("!(*.o)", "main.o"));
+        assert!(ext_matches("@(a|+(b))c", "bbbc"));
+        assert!(ext_matches("@(\\|)", "|"));
+    }
+
+    #[test]
+    fn test_pattern_extglob_disabled_or_unterminated() {
+        assert!(matches("@(a)", "@(a)"));
+        assert!(ext_matches("@(a", "@(a"));
+        assert!(ext_matches("?(a", "x(a"));
+    }
+}
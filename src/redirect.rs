@@ -0,0 +1,370 @@
+use crate::ast::{Redirect, RedirectKind};
+use crate::expand::{expand_heredoc, expand_string, expand_word};
+use crate::fds::{STDERR, STDOUT};
+use crate::shell::Shell;
+use crate::sys;
+use crate::sys::error_message;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, Write};
+use std::os::fd::OwnedFd;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Linux's default pipe capacity; smaller documents are written straight into a pipe.
+const PIPE_CAPACITY: usize = 65536;
+
+/// Runs `f` with `redirects` applied to the shell's fd table, restoring it afterwards.
+pub fn with_redirects<T>(
+    redirects: &[Redirect],
+    shell: &mut Shell,
+    f: impl FnOnce(&mut Shell) -> Result<T, String>,
+) -> Result<T, String> {
+    if redirects.is_empty() {
+        return f(shell);
+    }
+    let saved = shell.fds().clone();
+    let result = apply(redirects, shell).and_then(|()| f(shell));
+    *shell.fds_mut() = saved;
+    result
+}
+
+pub fn apply(redirects: &[Redirect], shell: &mut Shell) -> Result<(), String> {
+    for redirect in redirects {
+        apply_one(redirect, shell)?;
+    }
+    Ok(())
+}
+
+fn apply_one(redirect: &Redirect, shell: &mut Shell) -> Result<(), String> {
+    let mut options = OpenOptions::new();
+    match redirect.kind {
+        RedirectKind::Input => options.read(true),
+        RedirectKind::Output | RedirectKind::OutputAll => {
+            options.write(true).create(true).truncate(true)
+        }
+        RedirectKind::Append | RedirectKind::AppendAll => options.append(true).create(true),
+        RedirectKind::ReadWrite => options.read(true).write(true).create(true),
+        RedirectKind::DupInput | RedirectKind::DupOutput => {
+            let target = expand_target(redirect, shell)?;
+            return duplicate(redirect, &target, shell);
+        }
+        RedirectKind::HereDoc { expand } => {
+            let body = if expand {
+                expand_heredoc(&redirect.target, shell)?
+            } else {
+                redirect.target.clone()
+            };
+            let fd = here_document(&body)?;
+            shell.fds_mut().set(redirect.fd, Rc::new(fd));
+            return Ok(());
+        }
+        RedirectKind::HereString => {
+            let body = expand_string(&redirect.target, shell)?;
+            let fd = here_document(&format!("{body}\n"))?;
+            shell.fds_mut().set(redirect.fd, Rc::new(fd));
+            return Ok(());
+        }
+    };
+    let target = expand_target(redirect, shell)?;
+    let file = Rc::new(open(&target, &options)?);
+    if matches!(
+        redirect.kind,
+        RedirectKind::OutputAll | RedirectKind::AppendAll
+    ) {
+        shell.fds_mut().set(STDOUT, Rc::clone(&file));
+        shell.fds_mut().set(STDERR, file);
+    } else {
+        shell.fds_mut().set(redirect.fd, file);
+    }
+    Ok(())
+}
+
+// A redirect needs exactly one word to name its target.
+fn expand_target(redirect: &Redirect, shell: &mut Shell) -> Result<String, String> {
+    match expand_word(&redirect.target, shell)?.as_slice() {
+        [target] => Ok(target.clone()),
+        _ => Err(format!("{}: ambiguous redirect", redirect.target)),
+    }
+}
+
+fn open(path: &str, options: &OpenOptions) -> Result<OwnedFd, String> {
+    if path.is_empty() {
+        return Err(": No such file or directory".to_string());
+    }
+    options
+        .open(path)
+        .map(OwnedFd::from)
+        .map_err(|e| format!("{path}: {}", error_message(&e)))
+}
+
+/// Returns a readable descriptor that yields `body`, for here-documents and here-strings.
+fn here_document(body: &str) -> Result<OwnedFd, String> {
+    if body.len() < PIPE_CAPACITY {
+        let (read, write) = sys::pipe().map_err(|e| format!("pipe: {}", error_message(&e)))?;
+        File::from(write)
+            .write_all(body.as_bytes())
+            .map_err(|e| format!("here-document: {}", error_message(&e)))?;
+        return Ok(read);
+    }
+    // Larger bodies would fill the pipe before anyone reads it, so they go
+    // through an unlinked temporary file instead.
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = env::temp_dir().join(format!(
+        "shell-heredoc-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| {
+            fs::remove_file(&path)?;
+            file.write_all(body.as_bytes())?;
+            file.rewind()?;
+            Ok(file)
+        });
+    result
+        .map(OwnedFd::from)
+        .map_err(|e| format!("here-document: {}", error_message(&e)))
+}
+
+fn duplicate(redirect: &Redirect, target: &str, shell: &mut Shell) -> Result<(), String> {
+    if target == "-" {
+        shell.fds_mut().close(redirect.fd);
+        return Ok(());
+    }
+    if let Ok(source) = target.parse::<i32>() {
+        let file = shell
+            .fds()
+            .get(source)
+            .ok_or_else(|| format!("{source}: Bad file descriptor"))?;
+        shell.fds_mut().set(redirect.fd, file);
+        return Ok(());
+    }
+    // As in bash, `>&file` is another spelling of `&>file`.
+    if redirect.kind == RedirectKind::DupOutput && redirect.fd == STDOUT {
+        let all = Redirect {
+            kind: RedirectKind::OutputAll,
+            ..redirect.clone()
+        };
+        return apply_one(&all, shell);
+    }
+    Err(format!("{target}: ambiguous redirect"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exec::Run;
+    use crate::parser::parse;
+    use std::env;
+    use std::fs::{self, File};
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("shell-redirect-{}-{name}", std::process::id()))
+    }
+
+    fn run(input: &str, shell: &mut Shell) -> Result<i32, String> {
+        parse(input, shell.options().extglob)?.run(shell)
+    }
+
+    // `name` keeps the stderr file apart from those of tests running in parallel.
+    fn run_with_stderr(name: &str, input: &str) -> (i32, String) {
+        let path = temp_path(&format!("stderr-{name}"));
+        let mut shell = Shell::new();
+        let file = File::create(&path).unwrap();
+        shell.fds_mut().set(STDERR, Rc::new(file.into()));
+        let status = run(input, &mut shell).unwrap();
+        let stderr = fs::read_to_string(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        (status, stderr)
+    }
+
+    #[test]
+    fn test_redirect_builtin_output() {
+        let path = temp_path("builtin");
+        let mut shell = Shell::new();
+        run(&format!("echo hello > {}", path.display()), &mut shell).unwrap();
+        run(&format!("echo again >> {}", path.display()), &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello\nagain\n");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_redirect_external_input_and_output() {
+        let input = temp_path("input");
+        let output = temp_path("output");
+        fs::write(&input, "b\na\n").unwrap();
+        let mut shell = Shell::new();
+        let line = format!("sort < {} > {}", input.display(), output.display());
+        run(&line, &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "a\nb\n");
+        fs::remove_file(input).unwrap();
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn test_redirect_stderr_and_duplication() {
+        let path = temp_path("stderr");
+        let mut shell = Shell::new();
+        let line = format!("sh -c 'echo out; echo err >&2' > {} 2>&1", path.display());
+        run(&line, &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "out\nerr\n");
+
+        let line = format!(
+            "sh -c 'echo out; echo err >&2' 2> {} >/dev/null",
+            path.display()
+        );
+        run(&line, &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "err\n");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_redirect_binary_output_is_byte_exact() {
+        let path = temp_path("binary");
+        let mut shell = Shell::new();
+        let line = format!("printf '\\377\\000\\001\\r\\n' | cat > {}", path.display());
+        run(&line, &mut shell).unwrap();
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            vec![0xff, 0x00, 0x01, b'\r', b'\n']
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_redirect_output_all() {
+        let path = temp_path("all");
+        let mut shell = Shell::new();
+        let line = format!("sh -c 'echo out; echo err >&2' &> {}", path.display());
+        run(&line, &mut shell).unwrap();
+        let line = format!("sh -c 'echo more >&2' &>> {}", path.display());
+        run(&line, &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "out\nerr\nmore\n");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_redirect_numbered_fd() {
+        let path = temp_path("numbered");
+        let mut shell = Shell::new();
+        let line = format!("sh -c 'echo three >&3' 3> {}", path.display());
+        run(&line, &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "three\n");
+
+        let line = format!("{{ echo via4 >&4; }} 4>> {}", path.display());
+        run(&line, &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "three\nvia4\n");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_redirect_restores_fds() {
+        let path = temp_path("restore");
+        let mut shell = Shell::new();
+        run(&format!("echo a > {}", path.display()), &mut shell).unwrap();
+        assert!(shell.fds().get(STDOUT).is_some());
+        assert!(shell.fds().get(3).is_none());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_redirect_missing_input_names_path() {
+        let (status, stderr) = run_with_stderr("missing-input", "cat < /nonexistent/input.txt");
+        assert_eq!(status, 1);
+        assert_eq!(
+            stderr,
+            "Error: /nonexistent/input.txt: No such file or directory\n"
+        );
+    }
+
+    #[test]
+    fn test_redirect_bad_fd() {
+        let (status, stderr) = run_with_stderr("bad-fd", "echo hi >&7");
+        assert_eq!(status, 1);
+        assert_eq!(stderr, "Error: 7: Bad file descriptor\n");
+    }
+
+    #[test]
+    fn test_redirect_silences_command_errors() {
+        let (status, stderr) = run_with_stderr("silenced", "nonexistentcommand123 2>/dev/null");
+        assert_eq!(status, 127);
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn test_redirect_heredoc() {
+        let path = temp_path("heredoc");
+        let mut shell = Shell::new();
+        let line = format!(
+            "cat <<EOF > {}\nline one\n  line two\nEOF\n",
+            path.display()
+        );
+        run(&line, &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line one\n  line two\n");
+
+        let line = format!(
+            "tr a-z A-Z <<-END > {}\n\tindented\n\tEND\n",
+            path.display()
+        );
+        run(&line, &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "INDENTED\n");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_redirect_large_heredoc() {
+        let path = temp_path("large-heredoc");
+        let body = "x".repeat(99) + "\n";
+        let body = body.repeat(2000);
+        let mut shell = Shell::new();
+        let line = format!("cat <<EOF > {}\n{body}EOF\n", path.display());
+        run(&line, &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), body);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_redirect_here_string() {
+        let path = temp_path("herestring");
+        let mut shell = Shell::new();
+        let line = format!("cat <<< 'a  b' > {}", path.display());
+        run(&line, &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a  b\n");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_redirect_close_fd() {
+        let (status, stderr) = run_with_stderr("close-fd", "echo hi >&-");
+        assert_eq!(status, 1);
+        assert!(stderr.starts_with("Error: echo: write error"));
+    }
+
+    #[test]
+    fn test_redirect_expands_variables() {
+        let path = temp_path("vars");
+        let mut shell = Shell::new();
+        shell.vars_mut().set("OUT", path.display().to_string());
+        shell.vars_mut().set("WHO", "world".to_string());
+        run("cat <<EOF > $OUT\nhello $WHO\nEOF\n", &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello world\n");
+        run("cat <<'EOF' > \"$OUT\"\nhello $WHO\nEOF\n", &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello $WHO\n");
+        run("cat <<< \"$WHO  $WHO\" > ${OUT}", &mut shell).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "world  world\n");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_redirect_empty_target_is_ambiguous() {
+        let (status, stderr) = run_with_stderr("ambiguous", "echo hi > $UNSET_TARGET");
+        assert_eq!(status, 1);
+        assert_eq!(stderr, "Error: $UNSET_TARGET: ambiguous redirect\n");
+    }
+}
@@ -1,19 +1,282 @@
-use crate::cmd::{Cmd, Execute};
+use crate::ast::{Command, List};
+use crate::exec::Run;
+use crate::external::{COMMAND_NOT_EXECUTABLE, COMMAND_NOT_FOUND};
+use crate::fds::{FdTable, STDERR, STDIN, STDOUT, Stream};
 use crate::history::ShellHistory;
+use crate::options::ShellOptions;
+use crate::parser::{ParseError, parse};
+use crate::sys::{self, Pid};
+use crate::vars::Variables;
 use rustyline::{Config, Editor, error::ReadlineError};
+use std::collections::HashMap;
 use std::env;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::os::fd::OwnedFd;
+use std::process::ExitStatus;
+use std::rc::Rc;
+
+const CONTINUATION_PROMPT: &str = "> ";
+const SYNTAX_ERROR: i32 = 2;
+
+/// A pending `break` or `continue`, with the number of loops it still has to
+/// leave, a `return` from a function with its status, or an exit of the shell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    Break(usize),
+    Continue(usize),
+    Return(i32),
+    /// `exit` ran, or `-e` saw a command fail; the shell, or the forked child
+    /// running it, exits.
+    Exit(i32),
+    /// A fatal error abandons the rest of the command line, and a
+    /// non-interactive shell exits with the status.
+    Abort(i32),
+}
 
 pub struct Shell {
     history: ShellHistory,
+    fds: FdTable,
+    vars: Variables,
+    functions: HashMap<String, Rc<Command>>,
+    // `$0`, the shell's own name or that of the script it runs.
+    name: String,
+    // `$1` onwards; each function call gets its own.
+    positional: Vec<String>,
+    function_depth: usize,
+    // Files being run by `.`, which `return` may end.
+    source_depth: usize,
+    options: ShellOptions,
+    last_status: i32,
+    // Set by command substitutions, whose status a command made only of
+    // assignments reports as its own.
+    substitution_status: Option<i32>,
+    // The shell's ends of `<(...)` and `>(...)` pipes with their children,
+    // kept until the command that uses them finishes.
+    process_substitutions: Vec<(OwnedFd, Pid)>,
+    // Set by `break` and `continue`; lists stop running until a loop takes it.
+    control: Option<Control>,
+    loop_depth: usize,
+    // `-e` and `-x` from the command line.
+    errexit: bool,
+    xtrace: bool,
+    // Conditions being tested, whose failures do not trigger `-e`.
+    condition_depth: usize,
+    pid: u32,
 }
 
 impl Shell {
     pub fn new() -> Self {
         Shell {
             history: ShellHistory::new(),
+            fds: FdTable::inherited(),
+            vars: Variables::from_env(),
+            functions: HashMap::new(),
+            name: "shell".to_string(),
+            positional: Vec::new(),
+            function_depth: 0,
+            source_depth: 0,
+            options: ShellOptions::default(),
+            last_status: 0,
+            substitution_status: None,
+            process_substitutions: Vec::new(),
+            control: None,
+            loop_depth: 0,
+            errexit: false,
+            xtrace: false,
+            condition_depth: 0,
+            pid: std::process::id(),
+        }
+    }
+
+    pub fn vars(&self) -> &Variables {
+        &self.vars
+    }
+
+    pub fn vars_mut(&mut self) -> &mut Variables {
+        &mut self.vars
+    }
+
+    pub fn function(&self, name: &str) -> Option<Rc<Command>> {
+        self.functions.get(name).cloned()
+    }
+
+    pub fn define_function(&mut self, name: &str, body: Rc<Command>) {
+        self.functions.insert(name.to_string(), body);
+    }
+
+    pub fn unset_function(&mut self, name: &str) {
+        self.functions.remove(name);
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    /// Replaces the positional parameters, returning the old ones.
+    pub fn set_positional(&mut self, params: Vec<String>) -> Vec<String> {
+        mem::replace(&mut self.positional, params)
+    }
+
+    pub fn function_depth(&self) -> usize {
+        self.function_depth
+    }
+
+    pub fn enter_function(&mut self) {
+        self.function_depth += 1;
+    }
+
+    pub fn leave_function(&mut self) {
+        self.function_depth -= 1;
+    }
+
+    pub fn source_depth(&self) -> usize {
+        self.source_depth
+    }
+
+    pub fn options(&self) -> &ShellOptions {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut ShellOptions {
+        &mut self.options
+    }
+
+    /// The pid of the shell itself, which forked children keep reporting as `$$`.
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    pub fn last_status(&self) -> i32 {
+        self.last_status
+    }
+
+    pub fn set_last_status(&mut self, status: i32) {
+        self.last_status = status;
+    }
+
+    /// Maps the status of a foreground child the shell waited for. When the
+    /// user interrupted it, the rest of the command line is abandoned, as in bash.
+    pub fn foreground_status(&mut self, status: ExitStatus) -> i32 {
+        let code = sys::exit_code(status);
+        if sys::interrupted(status) {
+            self.control = Some(Control::Abort(code));
+        }
+        code
+    }
+
+    pub fn set_substitution_status(&mut self, status: i32) {
+        self.last_status = status;
+        self.substitution_status = Some(status);
+    }
+
+    pub fn take_substitution_status(&mut self) -> Option<i32> {
+        self.substitution_status.take()
+    }
+
+    pub fn control(&self) -> Option<Control> {
+        self.control
+    }
+
+    pub fn set_control(&mut self, control: Option<Control>) {
+        self.control = control;
+    }
+
+    pub fn take_control(&mut self) -> Option<Control> {
+        self.control.take()
+    }
+
+    pub fn loop_depth(&self) -> usize {
+        self.loop_depth
+    }
+
+    pub fn enter_loop(&mut self) {
+        self.loop_depth += 1;
+    }
+
+    pub fn leave_loop(&mut self) {
+        self.loop_depth -= 1;
+    }
+
+    pub fn errexit(&self) -> bool {
+        self.errexit
+    }
+
+    pub fn set_errexit(&mut self, on: bool) {
+        self.errexit = on;
+    }
+
+    pub fn xtrace(&self) -> bool {
+        self.xtrace
+    }
+
+    pub fn set_xtrace(&mut self, on: bool) {
+        self.xtrace = on;
+    }
+
+    pub fn condition_depth(&self) -> usize {
+        self.condition_depth
+    }
+
+    pub fn enter_condition(&mut self) {
+        self.condition_depth += 1;
+    }
+
+    pub fn leave_condition(&mut self) {
+        self.condition_depth -= 1;
+    }
+
+    pub fn add_process_substitution(&mut self, fd: OwnedFd, pid: Pid) {
+        self.process_substitutions.push((fd, pid));
+    }
+
+    pub fn process_substitution_count(&self) -> usize {
+        self.process_substitutions.len()
+    }
+
+    /// Closes the pipes of the process substitutions opened after the first
+    /// `keep`, then waits for their children.
+    pub fn reap_process_substitutions(&mut self, keep: usize) {
+        let (fds, pids): (Vec<OwnedFd>, Vec<Pid>) =
+            self.process_substitutions.drain(keep..).unzip();
+        drop(fds);
+        for pid in pids {
+            let _ = sys::wait(pid);
         }
     }
 
+    /// Closes inherited process substitution pipes in a forked child, which
+    /// cannot wait for their processes.
+    pub fn forget_process_substitutions(&mut self) {
+        self.process_substitutions.clear();
+    }
+
+    pub fn fds(&self) -> &FdTable {
+        &self.fds
+    }
+
+    pub fn fds_mut(&mut self) -> &mut FdTable {
+        &mut self.fds
+    }
+
+    pub fn stdout(&self) -> Stream {
+        self.fds.stream(STDOUT)
+    }
+
+    pub fn report_error(&self, err: &str) {
+        let _ = writeln!(self.fds.stream(STDERR), "Error: {err}");
+    }
+
     pub fn history(&self) -> &ShellHistory {
         &self.history
     }
@@ -23,28 +286,59 @@ impl Shell {
     }
 
     pub fn run(&mut self) {
+        sys::catch_interactive_signals();
         let config = Config::default();
         let history = ShellHistory::new();
         let mut rl: Editor<(), ShellHistory> = Editor::with_history(config, history).unwrap();
 
+        // Lines accumulate here until they form a complete command, so that
+        // here-documents and unfinished quotes can continue on the next line.
+        let mut input = String::new();
         loop {
-            let prompt = match env::current_dir() {
-                Ok(dir) => format!("{}$ ", dir.display()),
-                Err(_) => "$ ".to_string(),
+            let prompt = if !input.is_empty() {
+                CONTINUATION_PROMPT.to_string()
+            } else {
+                let status = match self.last_status {
+                    0 => String::new(),
+                    code => format!("[{code}] "),
+                };
+                match env::current_dir() {
+                    Ok(dir) => format!("{status}{}$ ", dir.display()),
+                    Err(_) => format!("{status}$ "),
+                }
             };
 
             match rl.readline(&prompt) {
                 Ok(line) => {
-                    let trimmed = line.trim();
-                    if !trimmed.is_empty()
-                        && let Err(e) = self.handle_command(trimmed)
-                    {
-                        eprintln!("Error: {e}");
+                    input.push_str(&line);
+                    input.push('\n');
+                    if matches!(self.parse_input(&input), Err(ParseError::Incomplete(_))) {
+                        continue;
+                    }
+                    if let Err(e) = self.handle_command(&input) {
+                        self.report_error(&e);
+                    }
+                    input.clear();
+                    match self.take_control() {
+                        Some(Control::Exit(status)) => {
+                            self.last_status = status;
+                            break;
+                        }
+                        Some(Control::Abort(status)) => self.last_status = status,
+                        _ => {}
                     }
                 }
 
-                Err(ReadlineError::Interrupted) => continue,
-                Err(ReadlineError::Eof) => break,
+                Err(ReadlineError::Interrupted) => {
+                    input.clear();
+                    continue;
+                }
+                Err(ReadlineError::Eof) => {
+                    if let Err(e) = self.parse_input(&input) {
+                        self.report_error(&e.to_string());
+                    }
+                    break;
+                }
                 Err(err) => {
                     eprintln!("Error: {:?}", err);
                     break;
@@ -52,24 +346,132 @@ impl Shell {
             }
         }
     }
-    fn parse_args(&self, args: &str) -> Vec<String> {
-        args.split_whitespace().map(|s| s.to_string()).collect()
+
+    /// Runs a script file, returning the status of its last command.
+    pub fn run_script(&mut self, path: &str) -> i32 {
+        match fs::read_to_string(path) {
+            Ok(source) => self.run_string(&source, path),
+            Err(e) => {
+                self.report_error(&format!("{path}: {}", sys::error_message(&e)));
+                if e.kind() == io::ErrorKind::NotFound {
+                    COMMAND_NOT_FOUND
+                } else {
+                    COMMAND_NOT_EXECUTABLE
+                }
+            }
+        }
+    }
+
+    /// Runs `source`, such as a `-c` command string, returning the status of
+    /// its last command. `origin` names it in syntax errors.
+    pub fn run_string(&mut self, source: &str, origin: &str) -> i32 {
+        let status = self.run_source(source, Some(origin));
+        self.exit_status(status)
     }
 
-    fn parse_input<'a>(&self, input: &'a str) -> (&'a str, &'a str) {
-        if let Some((cmd, args)) = input.split_once(" ") {
-            (cmd, args)
-        } else {
-            let args = "";
-            (input, args)
+    /// Runs commands from standard input without prompting, returning the
+    /// status of the last one.
+    pub fn run_stdin(&mut self) -> i32 {
+        let status = self.run_lines(
+            || {
+                let line = sys::read_line(STDIN)?;
+                Ok(line.map(|line| String::from_utf8_lossy(&line).into_owned()))
+            },
+            None,
+        );
+        self.exit_status(status)
+    }
+
+    /// Runs a file in the current shell, as `.` does. A `return` in it ends
+    /// the file; any other pending control is left for the caller.
+    pub fn source_file(&mut self, path: &str) -> Result<i32, String> {
+        let source =
+            fs::read_to_string(path).map_err(|e| format!("{path}: {}", sys::error_message(&e)))?;
+        self.source_depth += 1;
+        let status = self.run_source(&source, Some(path));
+        self.source_depth -= 1;
+        match self.control {
+            Some(Control::Return(status)) => {
+                self.control = None;
+                Ok(status)
+            }
+            _ => Ok(status),
         }
     }
 
+    /// Runs the commands of `source` in the current shell. A pending control,
+    /// such as a `return`, is left for the caller to take.
+    pub fn run_source(&mut self, source: &str, origin: Option<&str>) -> i32 {
+        let mut lines = source.split_inclusive('\n');
+        self.run_lines(|| Ok(lines.next().map(str::to_string)), origin)
+    }
+
+    // Runs each command as soon as the lines read so far complete it, like
+    // other shells do, so that a syntax error only stops what follows it.
+    fn run_lines(
+        &mut self,
+        mut next_line: impl FnMut() -> io::Result<Option<String>>,
+        origin: Option<&str>,
+    ) -> i32 {
+        let mut input = String::new();
+        let mut status = 0;
+        loop {
+            match next_line() {
+                Ok(Some(line)) => input.push_str(&line),
+                Ok(None) => break,
+                Err(e) => {
+                    self.report_error(&format!("read error: {}", sys::error_message(&e)));
+                    return 1;
+                }
+            }
+            match self.parse_input(&input) {
+                Err(ParseError::Incomplete(_)) => continue,
+                Err(e) => {
+                    self.report_syntax_error(&e, origin);
+                    return SYNTAX_ERROR;
+                }
+                Ok(program) => {
+                    input.clear();
+                    status = program.run(self).unwrap_or_else(|e| {
+                        self.report_error(&e);
+                        1
+                    });
+                    if self.control().is_some() {
+                        return status;
+                    }
+                }
+            }
+        }
+        if let Err(e) = self.parse_input(&input) {
+            self.report_syntax_error(&e, origin);
+            return SYNTAX_ERROR;
+        }
+        status
+    }
+
+    fn report_syntax_error(&self, err: &ParseError, origin: Option<&str>) {
+        match origin {
+            Some(origin) => self.report_error(&format!("{origin}: {err}")),
+            None => self.report_error(&err.to_string()),
+        }
+    }
+
+    // The status a non-interactive shell ends with, which an exit requested
+    // while running replaces.
+    fn exit_status(&mut self, status: i32) -> i32 {
+        match self.take_control() {
+            Some(Control::Exit(status) | Control::Abort(status)) => status,
+            _ => status,
+        }
+    }
+
+    fn parse_input(&self, input: &str) -> Result<List, ParseError> {
+        parse(input, self.options.extglob)
+    }
+
     fn handle_command(&mut self, input: &str) -> Result<(), String> {
-        let (cmd_name, args) = self.parse_input(input);
-        let args = self.parse_args(args);
-        let cmd = Cmd::new(cmd_name);
-        cmd.execute(&args, self)?;
+        let program = self.parse_input(input)?;
+        program.run(self)?;
         Ok(())
     }
 }
@@ -91,40 +493,47 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_args_single() {
+    fn test_parse_input_list() {
         let shell = Shell::new();
-        let result = shell.parse_args("hello");
-        assert_eq!(result, vec!["hello"]);
+        let program = shell.parse_input("echo hello world; exit").unwrap();
+        assert_eq!(program.items.len(), 2);
     }
 
     #[test]
-    fn test_parse_args_multiple() {
+    fn test_parse_input_empty() {
         let shell = Shell::new();
-        let result = shell.parse_args("hello world test");
-        assert_eq!(result, vec!["hello", "world", "test"]);
+        assert!(
+            shell
+                .parse_input("# only a comment")
+                .unwrap()
+                .items
+                .is_empty()
+        );
     }
 
     #[test]
-    fn test_parse_args_empty() {
+    fn test_parse_input_syntax_error() {
         let shell = Shell::new();
-        let result = shell.parse_args("");
-        assert!(result.is_empty());
+        assert!(shell.parse_input("echo a |").is_err());
     }
 
     #[test]
-    fn test_parse_input_with_args() {
-        let shell = Shell::new();
-        let (cmd, args) = shell.parse_input("echo hello world");
-        assert_eq!(cmd, "echo");
-        assert_eq!(args, "hello world");
+    fn test_handle_command_quoted_args() {
+        let mut shell = Shell::new();
+        let result = shell.handle_command(r#"echo "fix   bug" my\ file"#);
+        assert!(result.is_ok());
+        assert_eq!(
+            shell.history().iter().next().unwrap(),
+            "echo fix   bug my file"
+        );
     }
 
     #[test]
-    fn test_parse_input_no_args() {
-        let shell = Shell::new();
-        let (cmd, args) = shell.parse_input("exit");
-        assert_eq!(cmd, "exit");
-        assert_eq!(args, "");
+    fn test_handle_command_unterminated_quote() {
+        let mut shell = Shell::new();
+        let result = shell.handle_command("echo 'oops");
+        assert!(result.is_err());
+        assert!(shell.history().is_empty());
     }
 
     #[test]
@@ -139,6 +548,28 @@ mod tests {
     fn test_handle_command_unknown() {
         let mut shell = Shell::new();
         let result = shell.handle_command("nonexistentcommand123");
-        assert!(result.is_err());
+        assert!(result.is_ok());
+        assert_eq!(shell.last_status(), 127);
+    }
+
+    #[test]
+    fn test_run_string_stops_at_syntax_error() {
+        let mut shell = Shell::new();
+        let source = "x=1\nif true; then\n  y=2\nfi\nz=)\nw=4\n";
+        assert_eq!(shell.run_string(source, "script"), 2);
+        assert_eq!(shell.vars().get("x"), Some("1"));
+        assert_eq!(shell.vars().get("y"), Some("2"));
+        assert_eq!(shell.vars().get("w"), None);
+        assert_eq!(shell.run_string("false\n", "script"), 1);
+        assert_eq!(shell.run_string("if true; then", "script"), 2);
+    }
+
+    #[test]
+    fn test_handle_command_records_status() {
+        let mut shell = Shell::new();
+        shell.handle_command("false").unwrap();
+        assert_eq!(shell.last_status(), 1);
+        shell.handle_command("true").unwrap();
+        assert_eq!(shell.last_status(), 0);
     }
 }
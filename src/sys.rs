@@ -0,0 +1,135 @@
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+pub type Pid = libc::pid_t;
+
+pub fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    // SAFETY: `fds` is a valid buffer for the two descriptors pipe2 writes.
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: pipe2 succeeded, so both descriptors are open and owned by us.
+    unsafe { Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))) }
+}
+
+/// Clears close-on-exec, so that commands can still open the descriptor through `/dev/fd`.
+pub fn set_inheritable(fd: &OwnedFd) -> io::Result<()> {
+    let fd = fd.as_raw_fd();
+    // SAFETY: fcntl only reads and updates the flags of a descriptor we own.
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    // SAFETY: as above.
+    if flags == -1 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Reads one line from `fd`, newline included, or `None` at end of input.
+///
+/// The line is read a byte at a time so that input after it is left for the
+/// commands it runs, as a shell reading a script from a pipe must do.
+pub fn read_line(fd: RawFd) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    loop {
+        let mut byte = 0u8;
+        // SAFETY: `byte` is a valid one-byte buffer for read to write to.
+        match unsafe { libc::read(fd, (&mut byte as *mut u8).cast(), 1) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => return Ok((!line.is_empty()).then_some(line)),
+            _ => {
+                line.push(byte);
+                if byte == b'\n' {
+                    return Ok(Some(line));
+                }
+            }
+        }
+    }
+}
+
+/// Forks the process, returning `None` in the child and the child's pid in the parent.
+pub fn fork() -> io::Result<Option<Pid>> {
+    // SAFETY: the child only runs shell code and leaves through `exit_child`.
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(None),
+        pid => Ok(Some(pid)),
+    }
+}
+
+pub fn wait(pid: Pid) -> io::Result<ExitStatus> {
+    let mut status = 0;
+    loop {
+        // SAFETY: `status` is a valid pointer for waitpid to write to.
+        if unsafe { libc::waitpid(pid, &mut status, 0) } != -1 {
+            return Ok(ExitStatus::from_raw(status));
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Maps a child's exit status to the shell convention of 128 + N for signal N.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    match status.code() {
+        Some(code) => code,
+        None => 128 + status.signal().unwrap_or(0),
+    }
+}
+
+/// Whether a child was killed by Ctrl-C or Ctrl-\\.
+pub fn interrupted(status: ExitStatus) -> bool {
+    matches!(status.signal(), Some(libc::SIGINT | libc::SIGQUIT))
+}
+
+pub fn exit_child(code: i32) -> ! {
+    // SAFETY: _exit skips atexit handlers and stdio flushing inherited from the parent.
+    unsafe { libc::_exit(code) }
+}
+
+extern "C" fn ignore_signal(_: libc::c_int) {}
+
+/// Keeps an interactive shell alive when the user interrupts a foreground command.
+///
+/// A no-op handler is used rather than SIG_IGN because handlers are reset by
+/// exec, so external commands still get the default Ctrl-C and Ctrl-\ behaviour.
+pub fn catch_interactive_signals() {
+    // SAFETY: the handler is async-signal-safe because it does nothing.
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = ignore_signal as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        for signal in [libc::SIGINT, libc::SIGQUIT] {
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Restores the default dispositions a forked child should run with.
+pub fn reset_child_signals() {
+    // SAFETY: restoring default signal dispositions has no preconditions.
+    unsafe {
+        for signal in [libc::SIGPIPE, libc::SIGINT, libc::SIGQUIT] {
+            libc::signal(signal, libc::SIG_DFL);
+        }
+    }
+}
+
+/// Formats an OS error the way shells print it, without Rust's "(os error N)" suffix.
+pub fn error_message(err: &io::Error) -> String {
+    let message = err.to_string();
+    match message.find(" (os error") {
+        Some(end) => message[..end].to_string(),
+        None => message,
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::env;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub value: String,
+    pub exported: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    vars: HashMap<String, Variable>,
+    // One frame per running function, holding the values its `local`
+    // variables hid, to be put back when it returns.
+    scopes: Vec<Vec<(String, Option<Variable>)>>,
+}
+
+impl Variables {
+    pub fn from_env() -> Self {
+        let vars = env::vars()
+            .map(|(name, value)| {
+                let var = Variable {
+                    value,
+                    exported: true,
+                };
+                (name, var)
+            })
+            .collect();
+        Variables {
+            vars,
+            scopes: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(|var| var.value.as_str())
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<&Variable> {
+        self.vars.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, value: String) {
+        match self.vars.get_mut(name) {
+            Some(var) => var.value = value,
+            None => {
+                let var = Variable {
+                    value,
+                    exported: false,
+                };
+                self.vars.insert(name.to_string(), var);
+            }
+        }
+    }
+
+    /// Marks a variable for export, creating it empty if it does not exist yet.
+    pub fn export(&mut self, name: &str) {
+        self.vars
+            .entry(name.to_string())
+            .or_insert_with(|| Variable {
+                value: String::new(),
+                exported: false,
+            })
+            .exported = true;
+    }
+
+    /// Replaces a variable wholesale, which is how temporary assignments are undone.
+    pub fn restore(&mut self, name: &str, var: Option<Variable>) {
+        match var {
+            Some(var) => {
+                self.vars.insert(name.to_string(), var);
+            }
+            None => {
+                self.vars.remove(name);
+            }
+        }
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    /// Ends the innermost scope, giving its local variables back their old values.
+    pub fn pop_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            for (name, var) in scope.into_iter().rev() {
+                self.restore(&name, var);
+            }
+        }
+    }
+
+    /// Makes a variable local to the innermost scope, which is visible to the
+    /// functions it calls. Returns false outside of any scope.
+    pub fn make_local(&mut self, name: &str) -> bool {
+        let Some(scope) = self.scopes.last_mut() else {
+            return false;
+        };
+        if !scope.iter().any(|(local, _)| local == name) {
+            scope.push((name.to_string(), self.vars.get(name).cloned()));
+        }
+        true
+    }
+
+    pub fn unset(&mut self, name: &str) {
+        self.vars.remove(name);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.vars
+            .iter()
+            .map(|(name, var)| (name.as_str(), var.value.as_str()))
+    }
+
+    pub fn exported(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.vars
+            .iter()
+            .filter(|(_, var)| var.exported)
+            .map(|(name, var)| (name.as_str(), var.value.as_str()))
+    }
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variables_set_and_get() {
+        let mut vars = Variables::default();
+        assert_eq!(vars.get("FOO"), None);
+        vars.set("FOO", "bar".to_string());
+        assert_eq!(vars.get("FOO"), Some("bar"));
+        vars.set("FOO", "baz".to_string());
+        assert_eq!(vars.get("FOO"), Some("baz"));
+    }
+
+    #[test]
+    fn test_variables_export() {
+        let mut vars = Variables::default();
+        vars.set("A", "1".to_string());
+        vars.set("B", "2".to_string());
+        vars.export("A");
+        vars.export("C");
+        let mut exported: Vec<(&str, &str)> = vars.exported().collect();
+        exported.sort();
+        assert_eq!(exported, vec![("A", "1"), ("C", "")]);
+    }
+
+    #[test]
+    fn test_variables_set_keeps_export_flag() {
+        let mut vars = Variables::default();
+        vars.export("A");
+        vars.set("A", "new".to_string());
+        assert!(vars.get_var("A").unwrap().exported);
+    }
+
+    #[test]
+    fn test_variables_unset_and_restore() {
+        let mut vars = Variables::default();
+        vars.set("A", "1".to_string());
+        let saved = vars.get_var("A").cloned();
+        vars.unset("A");
+        assert_eq!(vars.get("A"), None);
+        vars.restore("A", saved);
+        assert_eq!(vars.get("A"), Some("1"));
+        vars.restore("A", None);
+        assert_eq!(vars.get("A"), None);
+    }
+
+    #[test]
+    fn test_variables_local_scopes() {
+        let mut vars = Variables::default();
+        assert!(!vars.make_local("A"));
+        vars.set("A", "global".to_string());
+        vars.push_scope();
+        assert!(vars.make_local("A"));
+        vars.set("A", "outer".to_string());
+        assert!(vars.make_local("B"));
+        vars.set("B", "new".to_string());
+        vars.push_scope();
+        assert!(vars.make_local("A"));
+        vars.set("A", "inner".to_string());
+        assert!(vars.make_local("A"));
+        vars.pop_scope();
+        assert_eq!(vars.get("A"), Some("outer"));
+        assert_eq!(vars.get("B"), Some("new"));
+        vars.pop_scope();
+        assert_eq!(vars.get("A"), Some("global"));
+        assert_eq!(vars.get("B"), None);
+    }
+
+    #[test]
+    fn test_variables_from_env() {
+        let vars = Variables::from_env();
+        if let Ok(path) = env::var("PATH") {
+            assert_eq!(vars.get("PATH"), Some(path.as_str()));
+            assert!(vars.get_var("PATH").unwrap().exported);
+        }
+    }
+
+    #[test]
+    fn test_is_valid_name() {
+        assert!(is_valid_name("FOO"));
+        assert!(is_valid_name("_x1"));
+        assert!(!is_valid_name("1x"));
+        assert!(!is_valid_name("a-b"));
+        assert!(!is_valid_name(""));
+    }
+}
@@ -3,7 +3,7 @@ use std::process::Command;
 #[test]
 fn test_shell_compiles() {
     let output = Command::new("cargo")
-        .args(&["build"])
+        .args(["build"])
         .output()
         .expect("Failed to execute cargo build");
 
@@ -30,3 +30,428 @@ fn test_shell_binary_exists() {
         release_path
     );
 }
+
+fn run_shell(input: &str) -> std::process::Output {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_shell"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start shell");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().expect("Failed to wait for shell")
+}
+
+#[test]
+fn test_shell_pipeline() {
+    let output = run_shell("echo hello | tr a-z A-Z | cat\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "HELLO\n");
+}
+
+#[test]
+fn test_shell_heredoc_across_lines() {
+    let output = run_shell("cat <<EOF | tr a-z A-Z\nfirst\nsecond\nEOF\necho 'multi\nline'\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "FIRST\nSECOND\nmulti\nline\n"
+    );
+}
+
+#[test]
+fn test_shell_if_across_lines() {
+    let output =
+        run_shell("if false\nthen\n  echo no\nelif true; then\n  echo yes\nfi\necho after\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "yes\nafter\n");
+}
+
+#[test]
+fn test_shell_function_across_lines() {
+    let output = run_shell("greet()\n{\n  echo \"hi $1\"\n}\ngreet there\ntype greet\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "hi there\ngreet : FUNCTION\n"
+    );
+}
+
+#[test]
+fn test_shell_subshell_keeps_directory() {
+    let output = run_shell("(cd / && pwd)\npwd\n");
+    let cwd = std::env::current_dir().unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "The current directory is: /\nThe current directory is: {}\n",
+            cwd.display()
+        )
+    );
+}
+
+#[test]
+fn test_shell_runs_script_file() {
+    let dir = std::env::temp_dir();
+    let path = dir.join(format!("shell-script-{}.sh", std::process::id()));
+    std::fs::write(
+        &path,
+        "#!/bin/shell\necho \"$0\" $# \"$1\"\nfor arg in \"$@\"; do echo \"[$arg]\"; done\nfalse\n",
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_shell"))
+        .args([path.to_str().unwrap(), "prod", "3 4"])
+        .output()
+        .expect("Failed to run script");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{} 2 prod\n[prod]\n[3 4]\n", path.display())
+    );
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_shell_script_runs_until_syntax_error() {
+    let path = std::env::temp_dir().join(format!("shell-broken-{}.sh", std::process::id()));
+    std::fs::write(&path, "echo one\necho two\nfi\necho three\n").unwrap();
+    let output = run_args(&[path.to_str().unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "one\ntwo\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("`fi'"));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_shell_script_line_continuation() {
+    let path = std::env::temp_dir().join(format!("shell-continued-{}.sh", std::process::id()));
+    std::fs::write(&path, "echo a \\\nb\necho c\\\nd\n").unwrap();
+    let output = run_args(&[path.to_str().unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "a b\ncd\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_shell_runs_shebang_script() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join(format!("shell-shebang-{}", std::process::id()));
+    let script = format!(
+        "#!{}\necho \"hello $1\"\nexit 7\n",
+        env!("CARGO_BIN_EXE_shell")
+    );
+    std::fs::write(&path, script).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let output = Command::new(&path)
+        .arg("world")
+        .output()
+        .expect("Failed to run script");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello world\n");
+    assert_eq!(output.status.code(), Some(7));
+}
+
+#[test]
+fn test_shell_missing_script() {
+    let output = Command::new(env!("CARGO_BIN_EXE_shell"))
+        .arg("/nonexistent/script.sh")
+        .output()
+        .expect("Failed to run shell");
+    assert_eq!(output.status.code(), Some(127));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("/nonexistent/script.sh"));
+}
+
+#[test]
+fn test_shell_external_stderr_reaches_user() {
+    let output = run_shell("ls /nonexistent-shell-test-dir\n");
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("nonexistent-shell-test-dir"));
+}
+
+#[test]
+fn test_shell_output_is_streamed_in_order() {
+    let output = run_shell("sh -c 'echo out; echo err >&2; echo more' 2>&1\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "out\nerr\nmore\n");
+}
+
+#[test]
+fn test_shell_binary_output_is_byte_exact() {
+    let output = run_shell("printf '\\377\\376\\000x'\n");
+    assert_eq!(output.stdout, vec![0xff, 0xfe, 0x00, b'x']);
+}
+
+#[test]
+fn test_shell_survives_interrupt_during_command() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_shell"))
+        .args(["-i", "--norc"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start shell");
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"sleep 1\necho alive\n").unwrap();
+    drop(stdin);
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "alive\n");
+}
+
+#[test]
+fn test_shell_interrupt_abandons_loop() {
+    use std::io::Write;
+    use std::os::unix::process::CommandExt;
+    use std::process::Stdio;
+
+    // The shell gets its own process group so that the signal reaches it and
+    // the foreground command together, as Ctrl-C at a terminal does.
+    let mut child = Command::new(env!("CARGO_BIN_EXE_shell"))
+        .args(["-i", "--norc"])
+        .process_group(0)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start shell");
+    let mut stdin = child.stdin.take().unwrap();
+    stdin
+        .write_all(b"f() { while true; do sleep 1; done; echo no; }\nf; echo no\necho after\n")
+        .unwrap();
+    drop(stdin);
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    Command::new("kill")
+        .args(["-INT", "--", &format!("-{}", child.id())])
+        .status()
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "after\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+fn run_args(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_shell"))
+        .args(args)
+        .output()
+        .expect("Failed to run shell")
+}
+
+#[test]
+fn test_shell_command_string() {
+    let output = run_args(&["-c", "echo \"$0 $# $1\"; exit 4", "name", "a b"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "name 1 a b\n");
+    assert_eq!(output.status.code(), Some(4));
+    let output = run_args(&["-c", "false"]);
+    assert_eq!(output.status.code(), Some(1));
+    let output = run_args(&["-c", "echo 'oops"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_shell_unset_parameter_error_is_fatal() {
+    let output = run_args(&["-c", "echo ${UNSET:?need it}; echo continued"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("UNSET: need it"));
+    assert_eq!(output.status.code(), Some(1));
+    let output = run_shell("f() { echo ${UNSET:?}; }\nfor i in 1 2; do f; done\necho continued\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_shell_interactive_unset_parameter_error_abandons_line() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_shell"))
+        .args(["-i", "--norc"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start shell");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"echo ${UNSET:?}; echo no\necho next\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "next\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_shell_interactive_line_continuation() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_shell"))
+        .args(["-i", "--norc"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start shell");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"echo a \\\nb\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "a b\n");
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("command not found"));
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_shell_reads_stdin_with_arguments() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_shell"))
+        .args(["-s", "x", "y"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start shell");
+    // `dd` reads the line after it from the same input, one byte at a time.
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"echo $2 $1\ndd bs=1 count=11 status=none\nfrom input\necho done\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "y x\nfrom input\ndone\n"
+    );
+}
+
+#[test]
+fn test_shell_errexit() {
+    let script = "if false; then :; fi; false || true; ! true; false && true
+        f() { false; echo ignored; }; f && echo tested
+        echo before; false; echo after";
+    let output = run_args(&["-e", "-c", script]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "ignored\ntested\nbefore\n"
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let output = run_args(&["-ec", "(exit 3); echo no"]);
+    assert_eq!(output.status.code(), Some(3));
+    let output = run_args(&["-ec", "cat <(echo in); (false; echo no); echo no"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "in\n");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_shell_xtrace() {
+    let output = run_args(&["-xc", "x='a b'; echo $x \"$x\" it\\'s"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "a b a b it's\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "+ echo a b 'a b' 'it'\\''s'\n"
+    );
+}
+
+#[test]
+fn test_shell_rcfile() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let rcfile = std::env::temp_dir().join(format!("shell-rc-{}", std::process::id()));
+    std::fs::write(
+        &rcfile,
+        "greeting=hello\nset -- one\n: done\nreturn\ngreeting=no\n",
+    )
+    .unwrap();
+    let run = |extra: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_shell"))
+            .args(["-i", "--rcfile", rcfile.to_str().unwrap()])
+            .args(extra.split_whitespace())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to start shell");
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(b"echo \"[$greeting$1]\"\n")
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    assert_eq!(run(""), "[helloone]\n");
+    assert_eq!(run("--norc"), "[]\n");
+    std::fs::remove_file(&rcfile).unwrap();
+}
+
+#[test]
+fn test_shell_sources_files() {
+    let path = std::env::temp_dir().join(format!("shell-source-{}.sh", std::process::id()));
+    std::fs::write(&path, "echo \"$1 $#\"\nreturn 3\necho no\n").unwrap();
+    let path = path.to_str().unwrap();
+    let output = run_args(&[
+        "-c",
+        ". \"$0\" a b; echo \"$? $# $1\"; source \"$0\"",
+        path,
+        "x",
+    ]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "a 2\n3 1 x\nx 1\n");
+    assert_eq!(output.status.code(), Some(3));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_shell_exit_unwinds() {
+    let script = "f() { while true; do (exit 5); echo \"$?\"; exit 3; done; }; f; echo no";
+    let output = run_args(&["-c", script]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "5\n");
+    assert_eq!(output.status.code(), Some(3));
+    let output = run_args(&["-c", "if exit 4; then echo no; fi; echo no"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(output.status.code(), Some(4));
+    let output = run_shell("echo $(exit 2; echo no) $?\nexit 6\necho no\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    assert_eq!(output.status.code(), Some(6));
+}
+
+#[test]
+fn test_shell_exit_in_startup_file() {
+    let rcfile = std::env::temp_dir().join(format!("shell-exit-rc-{}", std::process::id()));
+    std::fs::write(&rcfile, "echo rc\nexit 9\necho no\n").unwrap();
+    let output = run_args(&["-i", "--rcfile", rcfile.to_str().unwrap(), "-c", "echo no"]);
+    std::fs::remove_file(&rcfile).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "rc\n");
+    assert_eq!(output.status.code(), Some(9));
+}
+
+#[test]
+fn test_shell_help_version_and_bad_options() {
+    let output = run_args(&["--version"]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("shell {}\n", env!("CARGO_PKG_VERSION"))
+    );
+    let output = run_args(&["--help"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Usage: shell"));
+    let output = run_args(&["-q"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("shell: -q: invalid option\n"));
+}